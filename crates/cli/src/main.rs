@@ -1,12 +1,26 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use clap::{Parser, Subcommand, ValueEnum};
+use pixy_core::alpha::AlphaMode;
+use pixy_core::audio::{AudioCodec, AudioOptions, AudioSelection, Loudness};
+use pixy_core::container::Container;
+use pixy_core::dedup::DedupOptions;
 use pixy_core::devices::detect_vulkan_devices;
-use pixy_core::encoder::{EncoderKind, EncoderOptions};
+use pixy_core::encoder::{
+    auto_select_encoder, available_encoders, EncoderKind, EncoderOptions, GopOptions, RateControl,
+};
 use pixy_core::frames::{FrameExtractOptions, Prefilter};
-use pixy_core::models::{curated_models, ModelKind};
-use pixy_core::pipeline::{run_upscale_job, UpscaleJob};
-use pixy_core::upscalers::{find_upscaler_binary, UpscalerKind};
+use pixy_core::interpolate::{InterpolationOptions, InterpolationPlacement, InterpolationTarget};
+use pixy_core::models::{curated_models, ModelKind, ModelSpec};
+use pixy_core::pipeline::{encodes_video, run_upscale_job_with_progress, UpscaleJob};
+use pixy_core::probe::FrameRate;
+use pixy_core::scenes::{ModelRange, SceneModelSelection};
+use pixy_core::temporal::{StabilizeMethod, TemporalOptions};
+use pixy_core::upscalers::{
+    backend_for, cpu_fallback_reason, CpuResampler, ResampleFilter, TemplateBackend,
+    UpscaledFormat, UpscalerBackend, UpscalerOptions,
+};
 
 #[derive(Parser)]
 #[command(name = "pixy-uppy")]
@@ -19,18 +33,58 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     Devices,
+    /// Encoders the resolved ffmpeg can use, hardware encoders verified by a test encode
+    Encoders,
     Models,
-    Upscale(ArgsUpscale),
+    Upscale(Box<ArgsUpscale>),
 }
 
 #[derive(clap::Args)]
 struct ArgsUpscale {
+    /// Video file, image, directory of images, printf-style pattern (e.g. `scans/%04d.png`)
+    /// or `-` for stdin
     #[arg(short, long)]
     input: PathBuf,
+    /// Video file, image, directory, pattern or `-` for stdout; images are written
+    /// instead of a video when it looks like one
     #[arg(short, long)]
     output: PathBuf,
+    /// How transparent sources keep their alpha channel
+    #[arg(long, value_enum, default_value_t = Alpha::Resample)]
+    alpha: Alpha,
+    /// Output container; defaults to the output extension, or mkv with `-o -` (which
+    /// also takes webm or nut)
+    #[arg(long, value_enum)]
+    container: Option<Mux>,
+    /// Leave out all audio
+    #[arg(long, conflicts_with_all = ["audio_tracks", "audio_lang"])]
+    no_audio: bool,
+    /// Keep only these audio tracks (0 is the first audio track), e.g. 0,2
+    #[arg(long, value_delimiter = ',', conflicts_with = "audio_lang")]
+    audio_tracks: Vec<usize>,
+    /// Keep only audio tracks in these languages, e.g. eng,jpn
+    #[arg(long, value_delimiter = ',')]
+    audio_lang: Vec<String>,
+    /// Transcode audio instead of copying it
+    #[arg(long, value_enum)]
+    audio_codec: Option<ACodec>,
+    /// Audio bitrate in kbit/s (default 192 for AAC, 128 for Opus)
+    #[arg(long)]
+    audio_bitrate: Option<u32>,
+    /// Downmix audio to this many channels, e.g. 2 for stereo
+    #[arg(long)]
+    audio_channels: Option<u32>,
+    /// Normalise loudness in two passes (EBU R128, -23 LUFS unless a target is given)
+    #[arg(long, num_args = 0..=1, default_missing_value = "-23", allow_hyphen_values = true)]
+    loudnorm: Option<f64>,
+    /// Write the upscaled frames as images into the output directory instead of encoding
+    #[arg(long)]
+    image_sequence: bool,
     #[arg(short = 'm', long)]
     model: String,
+    /// TOML template describing an external upscaler to use instead of the built-ins
+    #[arg(long)]
+    backend_template: Option<PathBuf>,
     #[arg(long)]
     scale: Option<u32>,
     #[arg(long)]
@@ -43,14 +97,125 @@ struct ArgsUpscale {
     tile_size: Option<u32>,
     #[arg(long)]
     threads: Option<u32>,
-    #[arg(long, value_enum, default_value_t = Enc::HevcNvenc)]
+    #[arg(long)]
+    load_threads: Option<u32>,
+    #[arg(long)]
+    proc_threads: Option<u32>,
+    #[arg(long)]
+    save_threads: Option<u32>,
+    #[arg(long)]
+    tta: bool,
+    #[arg(long, value_enum)]
+    upscaled_format: Option<Fmt>,
+    #[arg(long)]
+    no_tile_fallback: bool,
+    /// Use the built-in CPU resampler instead of a GPU upscaler
+    #[arg(long)]
+    cpu: bool,
+    #[arg(long, value_enum, default_value_t = CpuFilter::Lanczos3)]
+    cpu_filter: CpuFilter,
+    /// Edge-directed sharpening for the CPU resampler (0 disables)
+    #[arg(long, default_value_t = 0.0)]
+    sharpen: f32,
+    /// TOML file mapping time ranges to models; other frames use --model
+    #[arg(long, conflicts_with = "auto_models")]
+    model_ranges: Option<PathBuf>,
+    /// Pick a model per scene by classifying it as animation or live action
+    #[arg(long)]
+    auto_models: bool,
+    #[arg(long, default_value = "realesr-animevideov3-x4")]
+    animation_model: String,
+    #[arg(long, default_value = "realesrgan-x4plus")]
+    live_action_model: String,
+    /// Upscale repeated frames only once (mpdecimate-style detection)
+    #[arg(long)]
+    dedup: bool,
+    /// Block difference above which a frame always counts as new
+    #[arg(long, default_value_t = 64 * 12)]
+    dedup_hi: u32,
+    /// Block difference above which a block counts as changed
+    #[arg(long, default_value_t = 64 * 5)]
+    dedup_lo: u32,
+    /// Fraction of changed blocks above which a frame counts as new
+    #[arg(long, default_value_t = 0.33)]
+    dedup_frac: f32,
+    /// Temporal stabilization of upscaled frames to reduce shimmer
+    #[arg(long, value_enum)]
+    temporal: Option<Temporal>,
+    /// Stabilization strength (0-1)
+    #[arg(long, default_value_t = 0.5)]
+    temporal_strength: f32,
+    /// Interpolate frames with RIFE, multiplying the frame rate (e.g. 2 for 24 -> 48 fps)
+    #[arg(long, conflicts_with = "interp_fps")]
+    interp_multiplier: Option<u32>,
+    /// Interpolate frames with RIFE to an explicit rate such as 60 or 60000/1001
+    #[arg(long, value_parser = parse_frame_rate)]
+    interp_fps: Option<FrameRate>,
+    #[arg(long, value_enum, default_value_t = Placement::After)]
+    interp_placement: Placement,
+    #[arg(long, default_value = "rife-v4.6")]
+    interp_model: String,
+    /// Scene change threshold (0-1) for --auto-models, --temporal and interpolation
+    #[arg(long, default_value_t = 0.3)]
+    scene_threshold: f32,
+    #[arg(long)]
+    no_scene_detect: bool,
+    /// RIFE UHD mode, recommended above 1080p
+    #[arg(long)]
+    interp_uhd: bool,
+    /// `auto` picks the hardware encoder of the --gpu vendor, else libx265/libx264
+    #[arg(long, value_enum, default_value_t = Enc::Auto)]
     encoder: Enc,
+    /// DRM render node for VAAPI/QSV encoders (default /dev/dri/renderD128)
+    #[arg(long)]
+    render_node: Option<PathBuf>,
     #[arg(long)]
     preset: Option<String>,
     #[arg(long)]
     tune: Option<String>,
-    #[arg(long)]
+    /// Constant quality (CRF, or the encoder's CQ/QP equivalent); lower is better
+    #[arg(long, conflicts_with = "bitrate")]
     crf: Option<u8>,
+    /// Lossless encode (libx264, libx265, libvpx-vp9; FFV1 always is)
+    #[arg(long, conflicts_with_all = ["crf", "bitrate"])]
+    lossless: bool,
+    /// Target video bitrate in kbit/s
+    #[arg(long)]
+    bitrate: Option<u32>,
+    /// Peak bitrate in kbit/s for VBR
+    #[arg(long, requires = "bitrate", conflicts_with = "cbr")]
+    maxrate: Option<u32>,
+    /// Hold the bitrate constant
+    #[arg(long, requires = "bitrate")]
+    cbr: bool,
+    /// Output size (e.g. 700M, 4.7G; K/M/G are powers of 1000, Ki/Mi/Gi of 1024);
+    /// two-pass on libx264, libx265, libvpx-vp9 and libaom-av1
+    #[arg(long, value_parser = parse_size, conflicts_with_all = ["crf", "bitrate", "lossless"])]
+    target_size: Option<u64>,
+    /// Maximum keyframe interval in frames
+    #[arg(long)]
+    keyint: Option<u32>,
+    /// Minimum keyframe interval in frames
+    #[arg(long)]
+    min_keyint: Option<u32>,
+    #[arg(long)]
+    bframes: Option<u32>,
+    /// Rate-control lookahead in frames
+    #[arg(long)]
+    lookahead: Option<u32>,
+    /// Encoder library params (`-x264-params`, `-x265-params`, `-svtav1-params`, `-aom-params`),
+    /// e.g. `aq-mode=3:psy-rd=2.0`; repeatable
+    #[arg(long, value_parser = parse_codec_params)]
+    codec_params: Vec<Vec<(String, String)>>,
+    /// Extra ffmpeg output option, e.g. `--ffmpeg-arg=-row-mt --ffmpeg-arg=1`; repeatable
+    #[arg(long = "ffmpeg-arg", allow_hyphen_values = true)]
+    ffmpeg_args: Vec<String>,
+    /// Output pixel format; defaults to the encoder's 8-bit (or --high-bit-depth) format
+    #[arg(long)]
+    pix_fmt: Option<String>,
+    /// 16-bit PNG intermediates and a 10-bit (or deeper) encode
+    #[arg(long)]
+    high_bit_depth: bool,
     #[arg(long, default_value = "png")]
     frame_format: String,
     #[arg(long, value_enum, default_value_t = Filter::None)]
@@ -59,31 +224,51 @@ struct ArgsUpscale {
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Enc {
+    Auto,
     H264Nvenc,
     HevcNvenc,
+    Av1Nvenc,
     H264Amf,
     HevcAmf,
+    Av1Amf,
     H264Qsv,
     HevcQsv,
+    Av1Qsv,
     H264Vaapi,
     HevcVaapi,
+    Av1Vaapi,
     Libx264,
     Libx265,
+    Libsvtav1,
+    LibaomAv1,
+    LibvpxVp9,
+    ProresKs,
+    Ffv1,
 }
 
 impl From<Enc> for EncoderKind {
     fn from(e: Enc) -> Self {
         match e {
+            Enc::Auto => unreachable!("resolved with auto_select_encoder"),
             Enc::H264Nvenc => EncoderKind::H264Nvenc,
             Enc::HevcNvenc => EncoderKind::HevcNvenc,
+            Enc::Av1Nvenc => EncoderKind::Av1Nvenc,
             Enc::H264Amf => EncoderKind::H264Amf,
             Enc::HevcAmf => EncoderKind::HevcAmf,
+            Enc::Av1Amf => EncoderKind::Av1Amf,
             Enc::H264Qsv => EncoderKind::H264Qsv,
             Enc::HevcQsv => EncoderKind::HevcQsv,
+            Enc::Av1Qsv => EncoderKind::Av1Qsv,
             Enc::H264Vaapi => EncoderKind::H264Vaapi,
             Enc::HevcVaapi => EncoderKind::HevcVaapi,
+            Enc::Av1Vaapi => EncoderKind::Av1Vaapi,
             Enc::Libx264 => EncoderKind::Libx264,
             Enc::Libx265 => EncoderKind::Libx265,
+            Enc::Libsvtav1 => EncoderKind::Libsvtav1,
+            Enc::LibaomAv1 => EncoderKind::LibaomAv1,
+            Enc::LibvpxVp9 => EncoderKind::LibvpxVp9,
+            Enc::ProresKs => EncoderKind::ProresKs,
+            Enc::Ffv1 => EncoderKind::Ffv1,
         }
     }
 }
@@ -107,6 +292,172 @@ impl From<Filter> for Prefilter {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Fmt {
+    Png,
+    Jpg,
+    Webp,
+}
+
+impl From<Fmt> for UpscaledFormat {
+    fn from(f: Fmt) -> Self {
+        match f {
+            Fmt::Png => UpscaledFormat::Png,
+            Fmt::Jpg => UpscaledFormat::Jpg,
+            Fmt::Webp => UpscaledFormat::Webp,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum CpuFilter {
+    Lanczos3,
+    Bicubic,
+    EwaLanczos,
+}
+
+impl From<CpuFilter> for ResampleFilter {
+    fn from(f: CpuFilter) -> Self {
+        match f {
+            CpuFilter::Lanczos3 => ResampleFilter::Lanczos3,
+            CpuFilter::Bicubic => ResampleFilter::Bicubic,
+            CpuFilter::EwaLanczos => ResampleFilter::EwaLanczos,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Alpha {
+    Resample,
+    Upscale,
+    Drop,
+}
+
+impl From<Alpha> for AlphaMode {
+    fn from(a: Alpha) -> Self {
+        match a {
+            Alpha::Resample => AlphaMode::Resample,
+            Alpha::Upscale => AlphaMode::Upscale,
+            Alpha::Drop => AlphaMode::Drop,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ACodec {
+    Aac,
+    Opus,
+    Flac,
+}
+
+impl From<ACodec> for AudioCodec {
+    fn from(c: ACodec) -> Self {
+        match c {
+            ACodec::Aac => AudioCodec::Aac,
+            ACodec::Opus => AudioCodec::Opus,
+            ACodec::Flac => AudioCodec::Flac,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Mux {
+    Mkv,
+    Mp4,
+    Mov,
+    Webm,
+    Nut,
+}
+
+impl From<Mux> for Container {
+    fn from(m: Mux) -> Self {
+        match m {
+            Mux::Mkv => Container::Mkv,
+            Mux::Mp4 => Container::Mp4,
+            Mux::Mov => Container::Mov,
+            Mux::Webm => Container::WebM,
+            Mux::Nut => Container::Nut,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Temporal {
+    Atadenoise,
+    Hqdn3d,
+    Tmix,
+    Deflicker,
+}
+
+impl From<Temporal> for StabilizeMethod {
+    fn from(t: Temporal) -> Self {
+        match t {
+            Temporal::Atadenoise => StabilizeMethod::Atadenoise,
+            Temporal::Hqdn3d => StabilizeMethod::Hqdn3d,
+            Temporal::Tmix => StabilizeMethod::Tmix,
+            Temporal::Deflicker => StabilizeMethod::Deflicker,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Placement {
+    Before,
+    After,
+}
+
+impl From<Placement> for InterpolationPlacement {
+    fn from(p: Placement) -> Self {
+        match p {
+            Placement::Before => InterpolationPlacement::BeforeUpscale,
+            Placement::After => InterpolationPlacement::AfterUpscale,
+        }
+    }
+}
+
+fn parse_codec_params(s: &str) -> Result<Vec<(String, String)>, String> {
+    s.split(':')
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+            _ => Err(format!("expected key=value, got `{pair}`")),
+        })
+        .collect()
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let scale: u64 = match unit.trim_end_matches(['B', 'b']) {
+        "" => 1,
+        "K" | "k" => 1_000,
+        "M" => 1_000_000,
+        "G" => 1_000_000_000,
+        "Ki" => 1 << 10,
+        "Mi" => 1 << 20,
+        "Gi" => 1 << 30,
+        _ => return Err(format!("unknown size unit `{unit}`")),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("invalid size `{s}`"))?;
+    Ok((number * scale as f64) as u64)
+}
+
+fn parse_frame_rate(s: &str) -> Result<FrameRate, String> {
+    FrameRate::parse(s).ok_or_else(|| format!("invalid frame rate `{s}`"))
+}
+
+/// Falls back to the CPU resampler when there is no hardware Vulkan device or the
+/// ncnn binary is missing, rather than failing the whole job.
+fn needs_cpu_fallback(upscaler: &dyn UpscalerBackend) -> bool {
+    let devices = detect_vulkan_devices().unwrap_or_default();
+    match cpu_fallback_reason(&devices, upscaler) {
+        Some(reason) => {
+            eprintln!("warning: {reason}; using the CPU resampler");
+            true
+        }
+        None => false,
+    }
+}
+
 /// CLI entrypoint. Why: Provide devices/models discovery and an `upscale` command.
 fn main() {
     let cli = Cli::parse();
@@ -117,58 +468,250 @@ fn main() {
                 println!("No devices detected (Vulkan runtime missing or no compatible GPUs)");
             }
             for d in devices {
-                println!("{}: {}", d.index, d.name);
+                match d.vendor() {
+                    Some(vendor) => println!("{}: {} ({})", d.index, d.name, vendor.name()),
+                    None => println!("{}: {}", d.index, d.name),
+                }
             }
         }
+        Commands::Encoders => match available_encoders() {
+            Ok(encoders) if encoders.is_empty() => println!("No usable encoders found"),
+            Ok(encoders) => {
+                for e in encoders {
+                    let kind = if e.is_hardware() {
+                        "hardware"
+                    } else {
+                        "software"
+                    };
+                    println!("{}\t({kind})", e.ffmpeg_name());
+                }
+            }
+            Err(e) => println!("Could not query ffmpeg: {e}"),
+        },
         Commands::Models => {
             for m in curated_models() {
-                let kind = match m.kind {
-                    ModelKind::RealEsrgan => "realesrgan",
-                    ModelKind::RealCugan => "realcugan",
-                    ModelKind::Waifu2x => "waifu2x",
-                };
-                println!("{}\t(kind: {}, scale: {}x)", m.name, kind, m.scale);
+                println!("{}\t(kind: {}, scale: {}x)", m.name, m.kind.id(), m.scale);
             }
         }
         Commands::Upscale(args) => {
-            let model = curated_models()
-                .into_iter()
-                .find(|m| m.name == args.model)
-                .expect("model not found");
-            let upscaler = match model.kind {
-                ModelKind::RealEsrgan => find_upscaler_binary(UpscalerKind::RealEsrgan),
-                ModelKind::RealCugan => find_upscaler_binary(UpscalerKind::RealCugan),
-                ModelKind::Waifu2x => find_upscaler_binary(UpscalerKind::Waifu2x),
-            }
-            .expect("upscaler binary not found");
+            let args = *args;
+            let (model, upscaler): (ModelSpec, Arc<dyn UpscalerBackend>) =
+                if let Some(path) = &args.backend_template {
+                    let backend = TemplateBackend::from_file(path).unwrap_or_else(|e| {
+                        eprintln!("error: invalid backend template {}: {}", path.display(), e);
+                        std::process::exit(1);
+                    });
+                    if let Err(e) = backend.find_binary() {
+                        eprintln!("error: {}", e);
+                        std::process::exit(1);
+                    }
+                    let model = ModelSpec {
+                        name: args.model.clone(),
+                        kind: ModelKind::Custom(backend.id().to_string()),
+                        scale: args
+                            .scale
+                            .or_else(|| backend.spec().scales.first().copied())
+                            .unwrap_or(2),
+                        denoise_level: None,
+                        path: None,
+                    };
+                    (model, Arc::new(backend))
+                } else {
+                    let model = curated_models()
+                        .into_iter()
+                        .find(|m| m.name == args.model)
+                        .expect("model not found");
+                    let upscaler = backend_for(&model.kind).expect("no backend for model");
+                    if args.cpu || needs_cpu_fallback(upscaler.as_ref()) {
+                        let cpu = CpuResampler {
+                            filter: args.cpu_filter.into(),
+                            sharpen: args.sharpen,
+                        };
+                        // The resampler only honours the scale of the selected model.
+                        let model = ModelSpec {
+                            denoise_level: None,
+                            ..model
+                        };
+                        (model, Arc::new(cpu))
+                    } else {
+                        (model, upscaler)
+                    }
+                };
 
+            let upscaler_uses_gpu = upscaler.capabilities().gpu_selection;
+            let find_model = |name: &str| {
+                curated_models()
+                    .into_iter()
+                    .find(|m| m.name == name)
+                    .expect("model not found")
+            };
+            let scene_models = if let Some(path) = &args.model_ranges {
+                let ranges = ModelRange::from_file(path).unwrap_or_else(|e| {
+                    eprintln!("error: invalid model ranges file {}: {}", path.display(), e);
+                    std::process::exit(1);
+                });
+                Some(SceneModelSelection::Ranges(ranges))
+            } else if args.auto_models {
+                Some(SceneModelSelection::Auto {
+                    threshold: args.scene_threshold,
+                    animation: find_model(&args.animation_model),
+                    live_action: find_model(&args.live_action_model),
+                })
+            } else {
+                None
+            };
+            let encoder = match args.encoder {
+                // Image, animation and sequence outputs never reach the encoder.
+                Enc::Auto if !encodes_video(&args.output, args.image_sequence) => {
+                    EncoderKind::Libx264
+                }
+                Enc::Auto => {
+                    let devices = detect_vulkan_devices().unwrap_or_default();
+                    match auto_select_encoder(
+                        devices.iter().find(|d| d.index == args.gpu),
+                        args.render_node.as_deref(),
+                    ) {
+                        Ok(choice) => {
+                            eprintln!("encoder: {}", choice.reason);
+                            choice.encoder
+                        }
+                        Err(e) => {
+                            eprintln!("error: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                fixed => fixed.into(),
+            };
+            let pix_fmt = match (&args.pix_fmt, args.high_bit_depth) {
+                (Some(pix_fmt), _) => pix_fmt.clone(),
+                (None, true) => match encoder.high_bit_depth_pix_fmt() {
+                    Some(pix_fmt) => pix_fmt.into(),
+                    None => {
+                        eprintln!(
+                            "error: {} is 8-bit only; use HEVC, AV1 or a software encoder",
+                            encoder.ffmpeg_name()
+                        );
+                        std::process::exit(1);
+                    }
+                },
+                (None, false) => encoder.supported_pix_fmts()[0].into(),
+            };
+            let rate_control = match (args.crf, args.bitrate) {
+                _ if args.lossless => Some(RateControl::Lossless),
+                (Some(q), _) => Some(RateControl::ConstantQuality(q)),
+                (None, Some(bitrate)) if args.cbr => Some(RateControl::Cbr { bitrate }),
+                (None, Some(bitrate)) => Some(match args.maxrate {
+                    Some(max_bitrate) => RateControl::Vbr {
+                        bitrate,
+                        max_bitrate,
+                    },
+                    None => RateControl::TargetBitrate(bitrate),
+                }),
+                (None, None) => args
+                    .target_size
+                    .map(|bytes| RateControl::TargetSize { bytes }),
+            };
+            let interp_target = match (args.interp_multiplier, args.interp_fps) {
+                (Some(m), _) => Some(InterpolationTarget::Multiplier(m)),
+                (None, Some(fps)) => Some(InterpolationTarget::Fps(fps)),
+                (None, None) => None,
+            };
+            let interpolation = interp_target.map(|target| InterpolationOptions {
+                target,
+                placement: args.interp_placement.into(),
+                model: args.interp_model.clone(),
+                scene_threshold: (!args.no_scene_detect).then_some(args.scene_threshold),
+                uhd: args.interp_uhd,
+            });
             let job = UpscaleJob {
                 input: args.input,
                 output: args.output,
                 model,
                 upscaler,
                 gpu_index: args.gpu,
-                tile_size: args.tile_size,
-                threads: args.threads,
+                upscaler_options: UpscalerOptions {
+                    tile_size: args.tile_size,
+                    load_threads: args.load_threads.or(args.threads),
+                    proc_threads: args.proc_threads.or(args.threads),
+                    save_threads: args.save_threads.or(args.threads),
+                    tta: args.tta,
+                    output_format: args.upscaled_format.map(Into::into),
+                },
+                tile_fallback: !args.no_tile_fallback && upscaler_uses_gpu,
                 target_width: args.width,
                 target_height: args.height,
                 scale: args.scale,
                 extract: FrameExtractOptions {
                     prefilter: args.prefilter.into(),
                     frame_format: args.frame_format,
+                    high_bit_depth: args.high_bit_depth,
+                    ..FrameExtractOptions::default()
                 },
                 encoder: EncoderOptions {
-                    encoder: args.encoder.into(),
+                    encoder,
                     preset: args.preset,
                     tune: args.tune,
-                    crf: args.crf,
-                    pix_fmt: Some("yuv420p".into()),
-                    container: None,
+                    rate_control,
+                    pix_fmt: Some(pix_fmt),
+                    render_node: args.render_node,
+                    gop: GopOptions {
+                        keyint: args.keyint,
+                        min_keyint: args.min_keyint,
+                        bframes: args.bframes,
+                        lookahead: args.lookahead,
+                    },
+                    codec_params: args.codec_params.into_iter().flatten().collect(),
+                    extra_args: args.ffmpeg_args,
+                },
+                container: args.container.map(Into::into),
+                audio: AudioOptions {
+                    selection: if args.no_audio {
+                        AudioSelection::None
+                    } else if !args.audio_tracks.is_empty() {
+                        AudioSelection::Indices(args.audio_tracks)
+                    } else if !args.audio_lang.is_empty() {
+                        AudioSelection::Languages(args.audio_lang)
+                    } else {
+                        AudioSelection::All
+                    },
+                    codec: args.audio_codec.map(Into::into),
+                    bitrate: args.audio_bitrate,
+                    channels: args.audio_channels,
+                    loudness: args.loudnorm.map(|integrated| Loudness {
+                        integrated,
+                        ..Loudness::default()
+                    }),
                 },
-                container: "mkv".into(),
+                alpha: args.alpha.into(),
+                image_output: args.image_sequence,
+                scene_models,
+                dedup: args.dedup.then_some(DedupOptions {
+                    hi: args.dedup_hi,
+                    lo: args.dedup_lo,
+                    frac: args.dedup_frac,
+                }),
+                temporal: args.temporal.map(|method| TemporalOptions {
+                    method: method.into(),
+                    strength: args.temporal_strength,
+                    scene_threshold: (!args.no_scene_detect).then_some(args.scene_threshold),
+                }),
+                interpolation,
+                work_dir: None,
+            };
+            let mut last = (String::new(), -1i32);
+            let mut on_progress = |p: pixy_core::UpscaleJobProgress| {
+                let percent = p.percent as i32;
+                if last.0 != p.stage || last.1 != percent {
+                    eprintln!("[{}] {}%", p.stage, percent);
+                    last = (p.stage, percent);
+                }
             };
-            if let Err(e) = run_upscale_job(&job) {
+            if let Err(e) = run_upscale_job_with_progress(&job, &mut on_progress) {
                 eprintln!("error: {}", e);
+                if let Some(failure) = e.upscaler_failure() {
+                    eprintln!("cause: {}", failure.describe());
+                }
                 std::process::exit(1);
             }
         }
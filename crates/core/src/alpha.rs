@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageBuffer, ImageDecoder, ImageReader, Rgb, Rgba};
+
+use crate::error::PixyError;
+use crate::upscalers::cpu::{image_error, list_sequence};
+use crate::upscalers::{CpuResampler, ResampleFilter};
+
+/// How transparency in the source is carried through the upscale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    /// Upscale the alpha plane with the CPU resampler; fast and free of model artifacts.
+    #[default]
+    Resample,
+    /// Run the alpha plane through the job's upscaler as a grey image.
+    Upscale,
+    /// Ignore transparency and upscale RGB only.
+    Drop,
+}
+
+/// Whether any frame of a sequence is stored with an alpha channel. Only the image
+/// headers are read, so opaque sequences skip the split without a full decode.
+pub fn sequence_has_alpha(pattern: &Path) -> Result<bool, PixyError> {
+    for frame in list_sequence(pattern)? {
+        let decoder = ImageReader::open(&frame)?
+            .with_guessed_format()?
+            .into_decoder()
+            .map_err(image_error)?;
+        if decoder.color_type().has_alpha() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Splits an RGBA sequence into an opaque RGB sequence in `rgb_dir` and its alpha plane,
+/// stored as grey RGB images, in `alpha_dir`. Returns both patterns, or `None` when every
+/// frame is fully opaque. 16-bit frames stay 16-bit.
+/// Why: ncnn models are trained on RGB and either drop alpha or smear it at edges.
+pub fn split_alpha(
+    input_pattern: &Path,
+    rgb_dir: &Path,
+    alpha_dir: &Path,
+) -> Result<Option<(PathBuf, PathBuf)>, PixyError> {
+    let frames = list_sequence(input_pattern)?;
+    let mut any_transparent = false;
+    std::fs::create_dir_all(rgb_dir)?;
+    std::fs::create_dir_all(alpha_dir)?;
+    for frame in &frames {
+        let name = Path::new(frame.file_stem().unwrap_or_default()).with_extension("png");
+        let source = image::open(frame).map_err(image_error)?;
+        let deep = is_deep(&source);
+        let rgba = source.to_rgba16();
+        any_transparent |= rgba.pixels().any(|p| p[3] < u16::MAX);
+        let (w, h) = rgba.dimensions();
+        let rgb = ImageBuffer::from_fn(w, h, |x, y| {
+            let p = rgba.get_pixel(x, y);
+            Rgb([p[0], p[1], p[2]])
+        });
+        let alpha = ImageBuffer::from_fn(w, h, |x, y| {
+            let a = rgba.get_pixel(x, y)[3];
+            Rgb([a, a, a])
+        });
+        save_rgb(rgb, deep, &rgb_dir.join(&name))?;
+        save_rgb(alpha, deep, &alpha_dir.join(&name))?;
+    }
+    if !any_transparent {
+        return Ok(None);
+    }
+    Ok(Some((rgb_dir.join("%08d.png"), alpha_dir.join("%08d.png"))))
+}
+
+/// Upscales an alpha sequence with a Catmull-Rom resampler into `output_dir`.
+/// Why: Lanczos ringing shows up as halos around cut-outs; bicubic stays clean.
+pub fn resample_alpha(
+    alpha_pattern: &Path,
+    output_dir: &Path,
+    scale: u32,
+) -> Result<PathBuf, PixyError> {
+    let resampler = CpuResampler {
+        filter: ResampleFilter::Bicubic,
+        sharpen: 0.0,
+    };
+    std::fs::create_dir_all(output_dir)?;
+    for frame in list_sequence(alpha_pattern)? {
+        let output = output_dir.join(frame.file_name().unwrap_or_default());
+        resampler.upscale_file(&frame, &output, scale)?;
+    }
+    Ok(output_dir.join("%08d.png"))
+}
+
+/// Recombines upscaled RGB frames with their upscaled alpha planes into RGBA PNGs in
+/// `output_dir`, resizing alpha when the upscaler's output size differs. The result is
+/// 16-bit when the RGB frames are.
+pub fn merge_alpha(
+    rgb_pattern: &Path,
+    alpha_pattern: &Path,
+    output_dir: &Path,
+) -> Result<PathBuf, PixyError> {
+    let alpha_dir = alpha_pattern.parent().unwrap_or(Path::new("."));
+    let alpha_ext = alpha_pattern.extension().unwrap_or_default();
+    std::fs::create_dir_all(output_dir)?;
+    for frame in list_sequence(rgb_pattern)? {
+        let stem = frame.file_stem().unwrap_or_default();
+        let source = image::open(&frame).map_err(image_error)?;
+        let deep = is_deep(&source);
+        let rgb = source.to_rgb16();
+        let (w, h) = rgb.dimensions();
+        let alpha_path = alpha_dir.join(stem).with_extension(alpha_ext);
+        let mut alpha = image::open(&alpha_path).map_err(image_error)?.to_luma16();
+        if alpha.dimensions() != (w, h) {
+            alpha = imageops::resize(&alpha, w, h, FilterType::CatmullRom);
+        }
+        let rgba = ImageBuffer::from_fn(w, h, |x, y| {
+            let p = rgb.get_pixel(x, y);
+            Rgba([p[0], p[1], p[2], alpha.get_pixel(x, y)[0]])
+        });
+        let rgba = DynamicImage::ImageRgba16(rgba);
+        let rgba = if deep {
+            rgba
+        } else {
+            DynamicImage::ImageRgba8(rgba.to_rgba8())
+        };
+        rgba.save(output_dir.join(stem).with_extension("png"))
+            .map_err(image_error)?;
+    }
+    Ok(output_dir.join("%08d.png"))
+}
+
+/// Whether an image has more than 8 bits per channel.
+fn is_deep(image: &DynamicImage) -> bool {
+    image.color().bytes_per_pixel() > image.color().channel_count()
+}
+
+fn save_rgb(
+    image: ImageBuffer<Rgb<u16>, Vec<u16>>,
+    deep: bool,
+    path: &Path,
+) -> Result<(), PixyError> {
+    let image = DynamicImage::ImageRgb16(image);
+    if deep {
+        image.save(path)
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8()).save(path)
+    }
+    .map_err(image_error)
+}
@@ -0,0 +1,274 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Frames};
+
+use crate::error::PixyError;
+use crate::frames::run_ffmpeg;
+use crate::images::is_sequence_pattern;
+use crate::probe::FrameRate;
+use crate::upscalers::cpu::image_error;
+
+/// Animated image containers the pipeline reads and writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    WebP,
+}
+
+/// How often an animation plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopCount {
+    Infinite,
+    /// Total number of plays, at least 1.
+    Plays(u16),
+}
+
+/// Timing of a decoded animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationInfo {
+    pub format: AnimationFormat,
+    /// Display time of every frame in milliseconds.
+    pub delays_ms: Vec<f64>,
+    pub loop_count: LoopCount,
+    /// Whether any frame has a pixel that is not fully opaque.
+    pub transparent: bool,
+}
+
+impl AnimationFormat {
+    /// Format to write for `output`: `.gif` and `.apng` always, `.png` and `.webp` only
+    /// for more than one frame (a single frame is written as a still image). Sequence
+    /// patterns such as `frames/%06d.png` and directories are image outputs, never
+    /// animations.
+    pub fn for_output(output: &Path, frame_count: usize) -> Option<Self> {
+        if is_sequence_pattern(output) || output.is_dir() {
+            return None;
+        }
+        let ext = output.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "apng" => Some(AnimationFormat::Apng),
+            "png" if frame_count > 1 => Some(AnimationFormat::Apng),
+            "webp" if frame_count > 1 => Some(AnimationFormat::WebP),
+            _ => None,
+        }
+    }
+}
+
+impl AnimationInfo {
+    /// Average frame rate, used by stages that expect a constant rate (interpolation).
+    pub fn average_rate(&self) -> FrameRate {
+        let total: f64 = self.delays_ms.iter().sum();
+        if total <= 0.0 {
+            return FrameRate::new(10, 1);
+        }
+        let fps = self.delays_ms.len() as f64 * 1000.0 / total;
+        FrameRate::new((fps * 1000.0).round().max(1.0) as u32, 1000)
+    }
+}
+
+/// Decodes an animated GIF, APNG or WebP into `frames_dir` as fully composited RGBA PNG
+/// frames numbered from 1, returning its timing. Returns `None` for anything that is
+/// not an animation (including still PNG and WebP files).
+pub fn decode_animation(
+    input: &Path,
+    frames_dir: &Path,
+) -> Result<Option<AnimationInfo>, PixyError> {
+    let Some(ext) = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+    else {
+        return Ok(None);
+    };
+    if !input.is_file() {
+        return Ok(None);
+    }
+    let reader =
+        || -> Result<BufReader<File>, PixyError> { Ok(BufReader::new(File::open(input)?)) };
+    let (format, frames): (AnimationFormat, Frames<'_>) = match ext.as_str() {
+        "gif" => (
+            AnimationFormat::Gif,
+            GifDecoder::new(reader()?)
+                .map_err(image_error)?
+                .into_frames(),
+        ),
+        "png" | "apng" => {
+            let decoder = PngDecoder::new(reader()?).map_err(image_error)?;
+            if !decoder.is_apng().map_err(image_error)? {
+                return Ok(None);
+            }
+            (
+                AnimationFormat::Apng,
+                decoder.apng().map_err(image_error)?.into_frames(),
+            )
+        }
+        "webp" => {
+            let decoder = WebPDecoder::new(reader()?).map_err(image_error)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            (AnimationFormat::WebP, decoder.into_frames())
+        }
+        _ => return Ok(None),
+    };
+
+    std::fs::create_dir_all(frames_dir)?;
+    let mut delays_ms = Vec::new();
+    let mut transparent = false;
+    for (i, frame) in frames.enumerate() {
+        let frame = frame.map_err(image_error)?;
+        let (num, den) = frame.delay().numer_denom_ms();
+        delays_ms.push(f64::from(num) / f64::from(den.max(1)));
+        let buffer = frame.into_buffer();
+        transparent |= buffer.pixels().any(|p| p[3] < u8::MAX);
+        buffer
+            .save(frames_dir.join(format!("{:08}.png", i + 1)))
+            .map_err(image_error)?;
+    }
+    if delays_ms.is_empty() {
+        return Err(PixyError::InvalidArgument("animation has no frames"));
+    }
+    let loop_count = read_loop_count(&std::fs::read(input)?, format);
+    Ok(Some(AnimationInfo {
+        format,
+        delays_ms,
+        loop_count,
+        transparent,
+    }))
+}
+
+/// Encodes `frames` into an animation with the given per-frame delays and loop count.
+/// Why: ffmpeg's concat demuxer keeps variable delays exactly, and for GIF a palette
+/// generated from the upscaled frames avoids the banding of the default 256-colour one.
+pub fn encode_animation(
+    frames: &[PathBuf],
+    delays_ms: &[f64],
+    loop_count: LoopCount,
+    format: AnimationFormat,
+    output: &Path,
+    work_dir: &Path,
+) -> Result<(), PixyError> {
+    let Some(last) = frames.last() else {
+        return Err(PixyError::InvalidArgument("no frames to encode"));
+    };
+    if delays_ms.len() != frames.len() {
+        return Err(PixyError::InvalidArgument(
+            "animation needs one delay per frame",
+        ));
+    }
+    let mut list = String::from("ffconcat version 1.0\n");
+    for (frame, delay) in frames.iter().zip(delays_ms) {
+        list.push_str(&format!(
+            "file '{}'\nduration {:.3}\n",
+            concat_escape(frame),
+            delay / 1000.0
+        ));
+    }
+    // The concat demuxer ignores the duration of the final entry unless it is repeated.
+    list.push_str(&format!("file '{}'\n", concat_escape(last)));
+    std::fs::create_dir_all(work_dir)?;
+    let list_path = work_dir.join("animation.ffconcat");
+    std::fs::write(&list_path, list)?;
+
+    let mut args: Vec<String> = vec![
+        "-y".into(),
+        "-f".into(),
+        "concat".into(),
+        "-safe".into(),
+        "0".into(),
+        "-i".into(),
+        list_path.to_string_lossy().to_string(),
+        "-vsync".into(),
+        "vfr".into(),
+    ];
+    match format {
+        AnimationFormat::Gif => {
+            args.extend([
+                "-filter_complex".into(),
+                "split[a][b];[a]palettegen=reserve_transparent=1:stats_mode=diff[p];[b][p]paletteuse=dither=sierra2_4a:alpha_threshold=128".into(),
+                "-f".into(),
+                "gif".into(),
+                // GIF counts repeats after the first play; -1 disables looping.
+                "-loop".into(),
+                match loop_count {
+                    LoopCount::Infinite => "0".into(),
+                    LoopCount::Plays(0 | 1) => "-1".into(),
+                    LoopCount::Plays(n) => (n - 1).to_string(),
+                },
+            ]);
+        }
+        AnimationFormat::Apng => args.extend([
+            "-f".into(),
+            "apng".into(),
+            "-plays".into(),
+            plays_arg(loop_count),
+        ]),
+        AnimationFormat::WebP => args.extend([
+            "-c:v".into(),
+            "libwebp_anim".into(),
+            "-lossless".into(),
+            "1".into(),
+            "-f".into(),
+            "webp".into(),
+            "-loop".into(),
+            plays_arg(loop_count),
+        ]),
+    }
+    args.push(output.to_string_lossy().to_string());
+
+    run_ffmpeg(&args)
+}
+
+/// APNG and WebP store total plays with 0 meaning forever.
+fn plays_arg(loop_count: LoopCount) -> String {
+    match loop_count {
+        LoopCount::Infinite => "0".into(),
+        LoopCount::Plays(n) => n.max(1).to_string(),
+    }
+}
+
+/// Reads the loop count from the container: the NETSCAPE2.0 extension for GIF, `acTL`
+/// for APNG and `ANIM` for WebP.
+fn read_loop_count(bytes: &[u8], format: AnimationFormat) -> LoopCount {
+    let after = |tag: &[u8]| {
+        bytes
+            .windows(tag.len())
+            .position(|w| w == tag)
+            .map(|i| &bytes[i + tag.len()..])
+    };
+    match format {
+        AnimationFormat::Gif => match after(b"NETSCAPE2.0") {
+            // Sub-block: size 3, id 1, little-endian repeat count (0 = forever).
+            Some([3, 1, lo, hi, ..]) => match u16::from_le_bytes([*lo, *hi]) {
+                0 => LoopCount::Infinite,
+                n => LoopCount::Plays(n.saturating_add(1)),
+            },
+            _ => LoopCount::Plays(1),
+        },
+        AnimationFormat::Apng => match after(b"acTL") {
+            Some([_, _, _, _, a, b, c, d, ..]) => match u32::from_be_bytes([*a, *b, *c, *d]) {
+                0 => LoopCount::Infinite,
+                n => LoopCount::Plays(n.min(u32::from(u16::MAX)) as u16),
+            },
+            _ => LoopCount::Infinite,
+        },
+        AnimationFormat::WebP => match after(b"ANIM") {
+            // Chunk size, background colour, then the loop count.
+            Some([_, _, _, _, _, _, _, _, lo, hi, ..]) => match u16::from_le_bytes([*lo, *hi]) {
+                0 => LoopCount::Infinite,
+                n => LoopCount::Plays(n),
+            },
+            _ => LoopCount::Infinite,
+        },
+    }
+}
+
+fn concat_escape(path: &Path) -> String {
+    path.to_string_lossy().replace('\'', r"'\''")
+}
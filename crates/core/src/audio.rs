@@ -0,0 +1,302 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::container::Container;
+use crate::error::PixyError;
+use crate::paths::resolve_tool;
+use crate::probe::{stream_bitrate, MediaInfo};
+
+/// Codecs audio can be transcoded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioCodec {
+    Aac,
+    Opus,
+    /// Lossless; takes no bitrate.
+    Flac,
+}
+
+impl AudioCodec {
+    /// ffmpeg encoder name.
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus",
+            AudioCodec::Flac => "flac",
+        }
+    }
+
+    /// Codec name as ffprobe reports it.
+    pub fn codec_name(&self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "opus",
+            AudioCodec::Flac => "flac",
+        }
+    }
+
+    /// Bitrate in kbit/s used when none is given; `None` for FLAC.
+    pub fn default_bitrate(&self) -> Option<u32> {
+        match self {
+            AudioCodec::Aac => Some(192),
+            AudioCodec::Opus => Some(128),
+            AudioCodec::Flac => None,
+        }
+    }
+}
+
+/// Which source audio tracks end up in the output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AudioSelection {
+    #[default]
+    All,
+    None,
+    /// Audio-relative indices, as in ffmpeg's `a:N`.
+    Indices(Vec<usize>),
+    /// ISO 639-2 language tags such as `eng` or `jpn`, matched case-insensitively.
+    Languages(Vec<String>),
+}
+
+/// EBU R128 loudness normalisation target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS.
+    pub integrated: f64,
+    /// Maximum true peak in dBTP.
+    pub true_peak: f64,
+    /// Loudness range in LU.
+    pub range: f64,
+}
+
+impl Default for Loudness {
+    /// The EBU R128 broadcast target.
+    fn default() -> Self {
+        Self {
+            integrated: -23.0,
+            true_peak: -1.0,
+            range: 7.0,
+        }
+    }
+}
+
+/// Audio policy of a job. The default copies every track.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioOptions {
+    pub selection: AudioSelection,
+    /// Transcode to this codec; `None` copies, unless the container cannot store the
+    /// source codec or another option needs decoded audio.
+    pub codec: Option<AudioCodec>,
+    /// Bitrate in kbit/s for lossy codecs.
+    pub bitrate: Option<u32>,
+    /// Downmix (or upmix) to this many channels.
+    pub channels: Option<u32>,
+    /// Two-pass `loudnorm` normalisation.
+    pub loudness: Option<Loudness>,
+}
+
+/// What happens to one selected source track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioTrack {
+    /// Audio-relative index in the source.
+    pub index: usize,
+    pub source_codec: String,
+    /// Codec to transcode to; `None` copies.
+    pub codec: Option<AudioCodec>,
+    /// Output bitrate in bits per second, if known.
+    pub bitrate: Option<u64>,
+    pub sample_rate: Option<u32>,
+}
+
+impl AudioOptions {
+    /// Whether the options need decoded audio, which rules out copying.
+    pub fn transcodes(&self) -> bool {
+        self.codec.is_some()
+            || self.bitrate.is_some()
+            || self.channels.is_some()
+            || self.loudness.is_some()
+    }
+
+    /// Checks the options against each other and against `container`.
+    pub fn validate(&self, container: Container) -> Result<(), PixyError> {
+        if self.bitrate == Some(0) || self.channels == Some(0) {
+            return Err(PixyError::InvalidArgument(
+                "audio bitrate and channels must be above 0",
+            ));
+        }
+        if self.codec == Some(AudioCodec::Flac) && self.bitrate.is_some() {
+            return Err(PixyError::InvalidArgument(
+                "FLAC is lossless and takes no bitrate",
+            ));
+        }
+        if let Some(codec) = self.codec {
+            if !container.supports_audio(codec.codec_name()) {
+                return Err(PixyError::InvalidArgument(
+                    "the container cannot store this audio codec (WebM needs Opus; MOV takes no Opus or FLAC)",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Selected tracks of `source` and how each one is written into `container`.
+    pub fn plan(&self, container: Container, source: &MediaInfo) -> Vec<AudioTrack> {
+        source
+            .audio_streams()
+            .enumerate()
+            .filter(|(index, stream)| match &self.selection {
+                AudioSelection::All => true,
+                AudioSelection::None => false,
+                AudioSelection::Indices(indices) => indices.contains(index),
+                AudioSelection::Languages(languages) => {
+                    let language = stream["tags"]["language"].as_str().unwrap_or_default();
+                    languages.iter().any(|l| l.eq_ignore_ascii_case(language))
+                }
+            })
+            .map(|(index, stream)| {
+                let source_codec = stream["codec_name"].as_str().unwrap_or("unknown");
+                let codec = if self.transcodes() {
+                    Some(self.codec.unwrap_or(container.default_audio_codec()))
+                } else if container.supports_audio(source_codec) {
+                    None
+                } else {
+                    Some(container.default_audio_codec())
+                };
+                let bitrate = match codec {
+                    None => stream_bitrate(stream),
+                    Some(codec) => self
+                        .bitrate
+                        .or(codec.default_bitrate())
+                        .map(|kbps| u64::from(kbps) * 1000),
+                };
+                AudioTrack {
+                    index,
+                    source_codec: source_codec.to_string(),
+                    codec,
+                    bitrate,
+                    sample_rate: stream["sample_rate"].as_str().and_then(|r| r.parse().ok()),
+                }
+            })
+            .collect()
+    }
+
+    /// ffmpeg output args writing `track` as output audio stream `out` of input 1,
+    /// normalised with `measured` from `measure_loudness` when loudness is set.
+    pub fn track_args(
+        &self,
+        track: &AudioTrack,
+        out: usize,
+        measured: Option<&LoudnessMeasurement>,
+    ) -> Vec<String> {
+        let mut args = vec!["-map".into(), format!("1:a:{}", track.index)];
+        let Some(codec) = track.codec else {
+            args.extend([format!("-c:a:{out}"), "copy".into()]);
+            return args;
+        };
+        args.extend([format!("-c:a:{out}"), codec.ffmpeg_name().into()]);
+        if let Some(bps) = track.bitrate.filter(|_| codec != AudioCodec::Flac) {
+            args.extend([format!("-b:a:{out}"), format!("{}k", bps / 1000)]);
+        }
+        if let Some(channels) = self.channels {
+            args.extend([format!("-ac:a:{out}"), channels.to_string()]);
+        }
+        if let Some(target) = &self.loudness {
+            // loudnorm works at 192 kHz internally and outputs that unless told otherwise.
+            let filter = format!(
+                "{},aresample={}",
+                loudnorm_filter(target, measured),
+                track.sample_rate.unwrap_or(48_000)
+            );
+            args.extend([format!("-filter:a:{out}"), filter]);
+        }
+        args
+    }
+}
+
+/// Loudness of a track as measured by the first `loudnorm` pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessMeasurement {
+    pub integrated: f64,
+    pub true_peak: f64,
+    pub range: f64,
+    pub threshold: f64,
+    pub offset: f64,
+}
+
+/// Measures audio track `index` of `input` against `target` (the first `loudnorm` pass).
+/// `None` when the track has no finite loudness, as silence measures `-inf`; such
+/// tracks get the single-pass filter.
+/// Why: Single-pass `loudnorm` adjusts gain dynamically; with the measured values the
+/// second pass applies one linear gain and keeps the dynamics intact.
+pub fn measure_loudness(
+    input: &Path,
+    index: usize,
+    target: &Loudness,
+) -> Result<Option<LoudnessMeasurement>, PixyError> {
+    let filter = format!("{}:print_format=json", loudnorm_filter(target, None));
+    let ffmpeg = resolve_tool("ffmpeg")?;
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(input)
+        .args(["-map", &format!("0:a:{index}"), "-af", &filter])
+        .args(["-f", "null", "-"])
+        .stdin(Stdio::null())
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let measurement = output
+        .status
+        .success()
+        .then(|| parse_loudnorm(&stderr))
+        .flatten();
+    let Some(measurement) = measurement else {
+        return Err(PixyError::ProcessFailed {
+            cmd: format!("ffmpeg loudnorm analysis of audio track {index}"),
+            code: output.status.code(),
+            stderr: stderr.into_owned(),
+        });
+    };
+    Ok(measurement.is_finite().then_some(measurement))
+}
+
+impl LoudnessMeasurement {
+    /// Whether every value is finite and can be passed back to `loudnorm`.
+    fn is_finite(&self) -> bool {
+        [
+            self.integrated,
+            self.true_peak,
+            self.range,
+            self.threshold,
+            self.offset,
+        ]
+        .iter()
+        .all(|v| v.is_finite())
+    }
+}
+
+/// Reads the JSON block `loudnorm=print_format=json` ends its log with.
+fn parse_loudnorm(log: &str) -> Option<LoudnessMeasurement> {
+    let start = log.rfind('{')?;
+    let end = start + log[start..].find('}')?;
+    let values: serde_json::Value = serde_json::from_str(&log[start..=end]).ok()?;
+    let value = |key: &str| values[key].as_str()?.trim().parse().ok();
+    Some(LoudnessMeasurement {
+        integrated: value("input_i")?,
+        true_peak: value("input_tp")?,
+        range: value("input_lra")?,
+        threshold: value("input_thresh")?,
+        offset: value("target_offset")?,
+    })
+}
+
+/// `loudnorm` filter for `target`, using `measured` values for a linear second pass.
+fn loudnorm_filter(target: &Loudness, measured: Option<&LoudnessMeasurement>) -> String {
+    let mut filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}",
+        target.integrated, target.true_peak, target.range
+    );
+    if let Some(m) = measured {
+        filter.push_str(&format!(
+            ":measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            m.integrated, m.true_peak, m.range, m.threshold, m.offset
+        ));
+    }
+    filter
+}
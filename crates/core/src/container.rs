@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use crate::audio::AudioCodec;
+use crate::encoder::EncoderKind;
+use crate::error::PixyError;
+
+/// Video containers the pipeline muxes into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Mkv,
+    Mp4,
+    Mov,
+    WebM,
+    Nut,
+}
+
+/// Text subtitle codecs that can be converted into another text format.
+const TEXT_SUBTITLES: &[&str] = &["subrip", "srt", "ass", "ssa", "webvtt", "mov_text", "text"];
+
+impl Container {
+    /// Container named by `output`'s extension.
+    pub fn for_output(output: &Path) -> Option<Self> {
+        let ext = output.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "mkv" => Some(Container::Mkv),
+            "mp4" | "m4v" => Some(Container::Mp4),
+            "mov" => Some(Container::Mov),
+            "webm" => Some(Container::WebM),
+            "nut" => Some(Container::Nut),
+            _ => None,
+        }
+    }
+
+    /// ffmpeg muxer name, passed with `-f`.
+    pub fn muxer(&self) -> &'static str {
+        match self {
+            Container::Mkv => "matroska",
+            Container::Mp4 => "mp4",
+            Container::Mov => "mov",
+            Container::WebM => "webm",
+            Container::Nut => "nut",
+        }
+    }
+
+    /// Whether the muxer can write to a pipe; MP4 and QuickTime seek back to write
+    /// their index.
+    pub fn is_streamable(&self) -> bool {
+        matches!(self, Container::Mkv | Container::WebM | Container::Nut)
+    }
+
+    /// Checks that the container can hold `encoder`'s video.
+    pub fn check_encoder(&self, encoder: EncoderKind) -> Result<(), PixyError> {
+        let vp9_or_av1 = matches!(
+            encoder,
+            EncoderKind::LibvpxVp9
+                | EncoderKind::Libsvtav1
+                | EncoderKind::LibaomAv1
+                | EncoderKind::Av1Nvenc
+                | EncoderKind::Av1Amf
+                | EncoderKind::Av1Qsv
+                | EncoderKind::Av1Vaapi
+        );
+        match self {
+            Container::WebM if !vp9_or_av1 => Err(PixyError::InvalidArgument(
+                "WebM only holds VP9 and AV1 video",
+            )),
+            Container::Mp4 if encoder.is_intra_only() => Err(PixyError::InvalidArgument(
+                "MP4 cannot hold ProRes or FFV1; use mov or mkv",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Codec a source subtitle stream is written with: `copy` when the container takes
+    /// it as is, a text format it can be converted to, or `None` when it has to be
+    /// dropped (bitmap subtitles outside Matroska).
+    pub fn subtitle_codec(&self, source_codec: &str) -> Option<&'static str> {
+        let text = TEXT_SUBTITLES.contains(&source_codec);
+        match self {
+            Container::Mkv => Some("copy"),
+            Container::Mp4 | Container::Mov if source_codec == "mov_text" => Some("copy"),
+            Container::Mp4 | Container::Mov if text => Some("mov_text"),
+            Container::WebM if source_codec == "webvtt" => Some("copy"),
+            Container::WebM if text => Some("webvtt"),
+            Container::Nut if text => Some("copy"),
+            _ => None,
+        }
+    }
+
+    /// Whether audio in `codec` (as ffprobe names it) can be copied in.
+    pub fn supports_audio(&self, codec: &str) -> bool {
+        match self {
+            Container::Mkv | Container::Nut => true,
+            Container::Mp4 => matches!(
+                codec,
+                "aac" | "mp3" | "ac3" | "eac3" | "alac" | "opus" | "flac"
+            ),
+            Container::Mov => {
+                matches!(codec, "aac" | "mp3" | "ac3" | "eac3" | "alac")
+                    || codec.starts_with("pcm_")
+            }
+            Container::WebM => matches!(codec, "opus" | "vorbis"),
+        }
+    }
+
+    /// Codec audio the container cannot store is transcoded to.
+    pub fn default_audio_codec(&self) -> AudioCodec {
+        match self {
+            Container::WebM => AudioCodec::Opus,
+            _ => AudioCodec::Aac,
+        }
+    }
+
+    /// Whether font and other attachments can be carried over.
+    pub fn supports_attachments(&self) -> bool {
+        *self == Container::Mkv
+    }
+
+    /// Muxer options for files.
+    /// Why: `+faststart` moves the MP4/QuickTime index to the front so playback can
+    /// start before the whole file is downloaded.
+    pub fn muxer_args(&self) -> Vec<String> {
+        match self {
+            Container::Mp4 | Container::Mov => vec!["-movflags".into(), "+faststart".into()],
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+use image::GrayImage;
+
+use crate::error::PixyError;
+use crate::upscalers::cpu::image_error;
+
+const BLOCK: u32 = 8;
+
+/// Duplicate detection thresholds, modelled on ffmpeg's `mpdecimate`: a frame duplicates
+/// the last unique frame when no 8x8 block differs by more than `hi` and at most `frac`
+/// of the blocks differ by more than `lo` (block differences are sums of absolute luma
+/// differences at the frame's own resolution; blocks at the right and bottom edges may
+/// be smaller).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DedupOptions {
+    pub hi: u32,
+    pub lo: u32,
+    pub frac: f32,
+}
+
+impl Default for DedupOptions {
+    fn default() -> Self {
+        Self {
+            hi: 64 * 12,
+            lo: 64 * 5,
+            frac: 0.33,
+        }
+    }
+}
+
+/// For every frame of a sequence, the index of the unique frame it repeats
+/// (its own index when the frame is unique).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateMap {
+    pub sources: Vec<usize>,
+}
+
+impl DuplicateMap {
+    pub fn is_unique(&self, index: usize) -> bool {
+        self.sources.get(index) == Some(&index)
+    }
+
+    pub fn unique_count(&self) -> usize {
+        (0..self.sources.len())
+            .filter(|&i| self.is_unique(i))
+            .count()
+    }
+}
+
+/// Compares each frame against the last unique one and records which frames repeat it.
+/// Why: Anime is commonly animated on twos or threes; upscaling each drawing once and
+/// reusing the result saves most of the GPU time without changing the output timing.
+pub fn find_duplicates(frames: &[PathBuf], opts: &DedupOptions) -> Result<DuplicateMap, PixyError> {
+    let mut sources = Vec::with_capacity(frames.len());
+    let mut last_unique: Option<(usize, GrayImage)> = None;
+    for (i, frame) in frames.iter().enumerate() {
+        let luma = image::open(frame).map_err(image_error)?.to_luma8();
+        match &last_unique {
+            Some((source, reference)) if is_duplicate(reference, &luma, opts) => {
+                sources.push(*source);
+            }
+            _ => {
+                sources.push(i);
+                last_unique = Some((i, luma));
+            }
+        }
+    }
+    Ok(DuplicateMap { sources })
+}
+
+/// Links the unique frames into `dir` under their original names and returns the
+/// pattern to upscale.
+pub fn stage_unique_frames(
+    frames: &[PathBuf],
+    map: &DuplicateMap,
+    dir: &Path,
+) -> Result<PathBuf, PixyError> {
+    std::fs::create_dir_all(dir)?;
+    for (i, frame) in frames.iter().enumerate() {
+        if !map.is_unique(i) {
+            continue;
+        }
+        link_or_copy(frame, &dir.join(frame.file_name().unwrap_or_default()))?;
+    }
+    let ext = frames
+        .first()
+        .and_then(|f| f.extension())
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".into());
+    Ok(dir.join(format!("%08d.{ext}")))
+}
+
+/// Fills in the upscaled frames of every duplicate from the frame it repeats, so
+/// `upscaled_dir` ends up with one file per source frame.
+pub fn restore_duplicates(
+    frames: &[PathBuf],
+    map: &DuplicateMap,
+    upscaled_dir: &Path,
+    extension: &str,
+) -> Result<(), PixyError> {
+    let upscaled = |frame: &Path| {
+        upscaled_dir
+            .join(frame.file_stem().unwrap_or_default())
+            .with_extension(extension)
+    };
+    for (i, frame) in frames.iter().enumerate() {
+        let source = map.sources[i];
+        if source != i {
+            link_or_copy(&upscaled(&frames[source]), &upscaled(frame))?;
+        }
+    }
+    Ok(())
+}
+
+/// Hard-links `src` to `dst`, copying when linking is not possible (e.g. across devices).
+pub(crate) fn link_or_copy(src: &Path, dst: &Path) -> Result<(), PixyError> {
+    if std::fs::hard_link(src, dst).is_err() {
+        std::fs::copy(src, dst)?;
+    }
+    Ok(())
+}
+
+fn is_duplicate(reference: &GrayImage, frame: &GrayImage, opts: &DedupOptions) -> bool {
+    if reference.dimensions() != frame.dimensions() {
+        return false;
+    }
+    let (width, height) = frame.dimensions();
+    let mut blocks = 0u32;
+    let mut over_lo = 0u32;
+    for by in (0..height).step_by(BLOCK as usize) {
+        for bx in (0..width).step_by(BLOCK as usize) {
+            let mut diff = 0u32;
+            for y in by..(by + BLOCK).min(height) {
+                for x in bx..(bx + BLOCK).min(width) {
+                    diff +=
+                        u32::from(reference.get_pixel(x, y)[0].abs_diff(frame.get_pixel(x, y)[0]));
+                }
+            }
+            if diff > opts.hi {
+                return false;
+            }
+            if diff > opts.lo {
+                over_lo += 1;
+            }
+            blocks += 1;
+        }
+    }
+    over_lo as f32 <= opts.frac * blocks as f32
+}
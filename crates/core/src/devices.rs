@@ -9,6 +9,48 @@ use crate::error::PixyError;
 pub struct VulkanDevice {
     pub index: usize,
     pub name: String,
+    /// PCI vendor ID reported by the driver (`VkPhysicalDeviceProperties::vendorID`).
+    pub vendor_id: u32,
+}
+
+/// GPU vendors with a hardware video encoder ffmpeg can drive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+    Intel,
+}
+
+impl GpuVendor {
+    /// Display name used in messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GpuVendor::Nvidia => "NVIDIA",
+            GpuVendor::Amd => "AMD",
+            GpuVendor::Intel => "Intel",
+        }
+    }
+}
+
+impl VulkanDevice {
+    /// Vendor from the PCI vendor ID; `None` for software rasterisers and other vendors.
+    pub fn vendor(&self) -> Option<GpuVendor> {
+        match self.vendor_id {
+            0x10de => Some(GpuVendor::Nvidia),
+            0x1002 | 0x1022 => Some(GpuVendor::Amd),
+            0x8086 => Some(GpuVendor::Intel),
+            _ => None,
+        }
+    }
+
+    /// True for software rasterisers (llvmpipe, lavapipe, SwiftShader), which are far
+    /// too slow for the ncnn upscalers.
+    pub fn is_software(&self) -> bool {
+        let name = self.name.to_ascii_lowercase();
+        ["llvmpipe", "lavapipe", "swiftshader"]
+            .iter()
+            .any(|s| name.contains(s))
+    }
 }
 
 /// Detects available Vulkan devices using the Vulkan loader. When the loader is
@@ -86,7 +128,11 @@ fn detect_vulkan_devices_native() -> Result<Vec<VulkanDevice>, NativeDetectError
                             .to_string_lossy()
                             .trim()
                             .to_string();
-                        VulkanDevice { index: idx, name }
+                        VulkanDevice {
+                            index: idx,
+                            name,
+                            vendor_id: props.vendor_id,
+                        }
                     })
                     .collect::<Vec<_>>();
                 Ok(list)
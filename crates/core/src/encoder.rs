@@ -1,67 +1,1114 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::container::Container;
+use crate::devices::{GpuVendor, VulkanDevice};
+use crate::error::PixyError;
+use crate::paths::resolve_tool;
+
 /// Supported encoders across vendors and software fallbacks.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EncoderKind {
     H264Nvenc,
     HevcNvenc,
+    Av1Nvenc,
     H264Amf,
     HevcAmf,
+    Av1Amf,
     H264Qsv,
     HevcQsv,
+    Av1Qsv,
     H264Vaapi,
     HevcVaapi,
+    Av1Vaapi,
     Libx264,
     Libx265,
+    /// SVT-AV1; presets are numeric, 0 (slowest) to 13.
+    Libsvtav1,
+    /// Reference AV1 encoder; presets map to `-cpu-used` 0 (slowest) to 8.
+    LibaomAv1,
+    /// Presets map to `-cpu-used` 0 (slowest) to 8.
+    LibvpxVp9,
+    /// Presets name the profile (`proxy`, `lt`, `standard`, `hq`, `4444`, `4444xq`);
+    /// 4444 when the source has alpha, otherwise 422 HQ.
+    ProresKs,
+    /// Lossless intra-only archive codec; takes no preset or rate control.
+    Ffv1,
+}
+
+/// Rate control, translated per encoder. Bitrates are in kbit/s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateControl {
+    /// Constant quality: CRF for software encoders, CQ/CQP/ICQ on hardware. Lower is better.
+    ConstantQuality(u8),
+    /// Constant bitrate.
+    Cbr { bitrate: u32 },
+    /// Variable bitrate averaging `bitrate` and capped at `max_bitrate`.
+    Vbr { bitrate: u32, max_bitrate: u32 },
+    /// Average bitrate without a cap; two passes on libx264, libx265, libvpx-vp9 and
+    /// libaom-av1, multipass or pre-analysis on NVENC and AMF.
+    TargetBitrate(u32),
+    /// Output size in bytes; the pipeline turns it into a `TargetBitrate` from the
+    /// source duration, less the audio it copies.
+    TargetSize { bytes: u64 },
+    /// Mathematically lossless (x264, x265, VP9, FFV1).
+    Lossless,
+}
+
+/// Keyframe and reference structure; `None` keeps the encoder's default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GopOptions {
+    /// Maximum distance between keyframes, in frames.
+    pub keyint: Option<u32>,
+    /// Minimum distance between keyframes, in frames.
+    pub min_keyint: Option<u32>,
+    /// Consecutive B-frames.
+    pub bframes: Option<u32>,
+    /// Frames the rate control looks ahead.
+    pub lookahead: Option<u32>,
+}
+
+/// Options that would undo the pipeline's own inputs, mapping, filters, codecs, codec
+/// params (set through `codec_params`) or two-pass statistics.
+const RESERVED_ARGS: &[&str] = &[
+    "-i",
+    "-y",
+    "-n",
+    "-f",
+    "-map",
+    "-c",
+    "-codec",
+    "-vcodec",
+    "-acodec",
+    "-scodec",
+    "-vf",
+    "-filter",
+    "-filter_complex",
+    "-lavfi",
+    "-af",
+    "-filter_script",
+    "-filter_complex_script",
+    "-x264-params",
+    "-x265-params",
+    "-svtav1-params",
+    "-aom-params",
+    "-pass",
+    "-passlogfile",
+    "-pix_fmt",
+    "-an",
+    "-vn",
+    "-sn",
+    "-dn",
+];
+
+/// Encoder API an `EncoderKind` goes through, which decides its option names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    Nvenc,
+    Amf,
+    Qsv,
+    Vaapi,
+    Software,
+}
+
+/// ProRes profiles accepted as a `prores_ks` preset.
+const PRORES_PROFILES: &[&str] = &["proxy", "lt", "standard", "hq", "4444", "4444xq"];
+
+impl EncoderKind {
+    /// Every encoder, hardware first in order of preference.
+    pub const ALL: [EncoderKind; 19] = [
+        EncoderKind::HevcNvenc,
+        EncoderKind::H264Nvenc,
+        EncoderKind::Av1Nvenc,
+        EncoderKind::HevcAmf,
+        EncoderKind::H264Amf,
+        EncoderKind::Av1Amf,
+        EncoderKind::HevcQsv,
+        EncoderKind::H264Qsv,
+        EncoderKind::Av1Qsv,
+        EncoderKind::HevcVaapi,
+        EncoderKind::H264Vaapi,
+        EncoderKind::Av1Vaapi,
+        EncoderKind::Libx265,
+        EncoderKind::Libx264,
+        EncoderKind::Libsvtav1,
+        EncoderKind::LibaomAv1,
+        EncoderKind::LibvpxVp9,
+        EncoderKind::ProresKs,
+        EncoderKind::Ffv1,
+    ];
+
+    fn family(&self) -> Family {
+        match self {
+            EncoderKind::H264Nvenc | EncoderKind::HevcNvenc | EncoderKind::Av1Nvenc => {
+                Family::Nvenc
+            }
+            EncoderKind::H264Amf | EncoderKind::HevcAmf | EncoderKind::Av1Amf => Family::Amf,
+            EncoderKind::H264Qsv | EncoderKind::HevcQsv | EncoderKind::Av1Qsv => Family::Qsv,
+            EncoderKind::H264Vaapi | EncoderKind::HevcVaapi | EncoderKind::Av1Vaapi => {
+                Family::Vaapi
+            }
+            _ => Family::Software,
+        }
+    }
+
+    /// Whether the encoder runs on a GPU or media engine.
+    pub fn is_hardware(&self) -> bool {
+        self.family() != Family::Software
+    }
+
+    /// Whether frames must be uploaded to a VAAPI/QSV hardware device before encoding.
+    pub fn uses_hw_upload(&self) -> bool {
+        matches!(self.family(), Family::Qsv | Family::Vaapi)
+    }
+
+    /// Encoder name as passed to ffmpeg's `-c:v`.
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            EncoderKind::H264Nvenc => "h264_nvenc",
+            EncoderKind::HevcNvenc => "hevc_nvenc",
+            EncoderKind::Av1Nvenc => "av1_nvenc",
+            EncoderKind::H264Amf => "h264_amf",
+            EncoderKind::HevcAmf => "hevc_amf",
+            EncoderKind::Av1Amf => "av1_amf",
+            EncoderKind::H264Qsv => "h264_qsv",
+            EncoderKind::HevcQsv => "hevc_qsv",
+            EncoderKind::Av1Qsv => "av1_qsv",
+            EncoderKind::H264Vaapi => "h264_vaapi",
+            EncoderKind::HevcVaapi => "hevc_vaapi",
+            EncoderKind::Av1Vaapi => "av1_vaapi",
+            EncoderKind::Libx264 => "libx264",
+            EncoderKind::Libx265 => "libx265",
+            EncoderKind::Libsvtav1 => "libsvtav1",
+            EncoderKind::LibaomAv1 => "libaom-av1",
+            EncoderKind::LibvpxVp9 => "libvpx-vp9",
+            EncoderKind::ProresKs => "prores_ks",
+            EncoderKind::Ffv1 => "ffv1",
+        }
+    }
+
+    /// Legal values for `RateControl::ConstantQuality`; `None` for FFV1.
+    pub fn quality_range(&self) -> Option<RangeInclusive<u8>> {
+        match self {
+            EncoderKind::Ffv1 => None,
+            EncoderKind::ProresKs => Some(0..=32),
+            // AV1 quantisers on AMF and VAAPI use the bitstream's own 0-255 scale.
+            EncoderKind::Av1Amf | EncoderKind::Av1Vaapi => Some(0..=255),
+            EncoderKind::Libsvtav1
+            | EncoderKind::LibaomAv1
+            | EncoderKind::LibvpxVp9
+            | EncoderKind::Av1Nvenc => Some(0..=63),
+            _ if self.family() == Family::Qsv => Some(1..=51),
+            _ => Some(0..=51),
+        }
+    }
+
+    /// Whether the encoder can target a bitrate (ProRes and FFV1 cannot).
+    pub fn supports_bitrate(&self) -> bool {
+        !matches!(self, EncoderKind::ProresKs | EncoderKind::Ffv1)
+    }
+
+    /// Whether `RateControl::Lossless` is available.
+    pub fn supports_lossless(&self) -> bool {
+        matches!(
+            self,
+            EncoderKind::Libx264
+                | EncoderKind::Libx265
+                | EncoderKind::LibvpxVp9
+                | EncoderKind::Ffv1
+        )
+    }
+
+    /// Checks `preset` against the encoder's own preset scale.
+    fn validate_preset(&self, preset: &str) -> Result<(), PixyError> {
+        let numeric = |max: u8| preset.parse::<u8>().is_ok_and(|p| p <= max);
+        let valid = match self {
+            EncoderKind::Libsvtav1 => numeric(13),
+            EncoderKind::LibaomAv1 | EncoderKind::LibvpxVp9 => numeric(8),
+            EncoderKind::ProresKs => PRORES_PROFILES.contains(&preset),
+            EncoderKind::Ffv1 => false,
+            _ => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(PixyError::InvalidArgument(
+                "invalid preset for this encoder (0-13 for SVT-AV1, 0-8 for libaom and VP9, a profile name for ProRes, none for FFV1)",
+            ))
+        }
+    }
+
+    /// ffmpeg arguments selecting `preset`; ProRes profiles are handled with alpha.
+    fn preset_args(&self, preset: &str) -> Vec<String> {
+        match self {
+            EncoderKind::LibaomAv1 => vec!["-cpu-used".into(), preset.into()],
+            EncoderKind::LibvpxVp9 => vec![
+                "-deadline".into(),
+                "good".into(),
+                "-cpu-used".into(),
+                preset.into(),
+            ],
+            EncoderKind::ProresKs | EncoderKind::Ffv1 => Vec::new(),
+            _ => vec!["-preset".into(), preset.into()],
+        }
+    }
+
+    /// ffmpeg arguments implementing `rc` with this encoder's own options.
+    /// Why: `-crf` is only understood by the software encoders; NVENC, AMF, QSV and
+    /// VAAPI silently ignore it and fall back to their default bitrate.
+    fn rate_control_args(&self, rc: RateControl) -> Vec<String> {
+        let kbps = |v: u32| format!("{v}k");
+        let mut args: Vec<String> = Vec::new();
+        let mut push = |pairs: &[(&str, String)]| {
+            for (k, v) in pairs {
+                args.push((*k).into());
+                args.push(v.clone());
+            }
+        };
+        let family = self.family();
+        match (self, rc) {
+            // Neither has bitrate control; `EncoderOptions::validate` rejects the rest.
+            (EncoderKind::ProresKs, RateControl::ConstantQuality(q)) => {
+                push(&[("-qscale:v", q.to_string())])
+            }
+            (EncoderKind::ProresKs | EncoderKind::Ffv1, _) => {}
+            (EncoderKind::Libx264, RateControl::Lossless) => push(&[("-qp", "0".into())]),
+            // Goes through `-x265-params` with the other codec params; see `codec_params`.
+            (EncoderKind::Libx265, RateControl::Lossless) => {}
+            (EncoderKind::LibvpxVp9, RateControl::Lossless) => push(&[("-lossless", "1".into())]),
+            (_, RateControl::Lossless) => {}
+            (EncoderKind::LibvpxVp9 | EncoderKind::LibaomAv1, RateControl::ConstantQuality(q)) => {
+                // libvpx and libaom only honour -crf as constant quality with no bitrate cap.
+                push(&[("-crf", q.to_string()), ("-b:v", "0".into())])
+            }
+            (_, RateControl::ConstantQuality(q)) => match family {
+                Family::Nvenc => push(&[
+                    ("-rc", "vbr".into()),
+                    ("-cq", q.to_string()),
+                    ("-b:v", "0".into()),
+                ]),
+                // AV1 on AMF has no B-frames, so no B-frame quantiser either.
+                Family::Amf if *self == EncoderKind::Av1Amf => push(&[
+                    ("-rc", "cqp".into()),
+                    ("-qp_i", q.to_string()),
+                    ("-qp_p", q.to_string()),
+                ]),
+                Family::Amf => push(&[
+                    ("-rc", "cqp".into()),
+                    ("-qp_i", q.to_string()),
+                    ("-qp_p", q.to_string()),
+                    ("-qp_b", q.to_string()),
+                ]),
+                Family::Qsv => push(&[("-global_quality", q.to_string())]),
+                Family::Vaapi => push(&[("-rc_mode", "CQP".into()), ("-qp", q.to_string())]),
+                Family::Software => push(&[("-crf", q.to_string())]),
+            },
+            (_, RateControl::Cbr { bitrate }) => {
+                match family {
+                    Family::Nvenc | Family::Amf => push(&[("-rc", "cbr".into())]),
+                    Family::Vaapi => push(&[("-rc_mode", "CBR".into())]),
+                    Family::Qsv | Family::Software => {}
+                }
+                push(&[
+                    ("-b:v", kbps(bitrate)),
+                    ("-minrate", kbps(bitrate)),
+                    ("-maxrate", kbps(bitrate)),
+                    ("-bufsize", kbps(bitrate.saturating_mul(2))),
+                ]);
+            }
+            (
+                _,
+                RateControl::Vbr {
+                    bitrate,
+                    max_bitrate,
+                },
+            ) => {
+                match family {
+                    Family::Nvenc => push(&[("-rc", "vbr".into())]),
+                    Family::Amf => push(&[("-rc", "vbr_peak".into())]),
+                    Family::Vaapi => push(&[("-rc_mode", "VBR".into())]),
+                    Family::Qsv | Family::Software => {}
+                }
+                push(&[
+                    ("-b:v", kbps(bitrate)),
+                    ("-maxrate", kbps(max_bitrate)),
+                    ("-bufsize", kbps(max_bitrate.saturating_mul(2))),
+                ]);
+            }
+            // Only meaningful once resolved; see `EncoderOptions::resolve_target_size`.
+            (_, RateControl::TargetSize { .. }) => {}
+            (_, RateControl::TargetBitrate(bitrate)) => {
+                // Hardware encoders have no second pass; they analyse ahead within one.
+                match family {
+                    Family::Nvenc => {
+                        push(&[("-rc", "vbr".into()), ("-multipass", "fullres".into())])
+                    }
+                    Family::Amf => {
+                        push(&[("-rc", "vbr_latency".into()), ("-preanalysis", "1".into())])
+                    }
+                    Family::Vaapi => push(&[("-rc_mode", "VBR".into())]),
+                    Family::Qsv | Family::Software => {}
+                }
+                push(&[("-b:v", kbps(bitrate))]);
+            }
+        }
+        args
+    }
+
+    /// Whether `RateControl::TargetBitrate` runs as two ffmpeg passes.
+    pub fn supports_two_pass(&self) -> bool {
+        matches!(
+            self,
+            EncoderKind::Libx264
+                | EncoderKind::Libx265
+                | EncoderKind::LibvpxVp9
+                | EncoderKind::LibaomAv1
+        )
+    }
+
+    /// Whether every frame is a keyframe, which leaves no GOP to configure.
+    pub fn is_intra_only(&self) -> bool {
+        matches!(self, EncoderKind::ProresKs | EncoderKind::Ffv1)
+    }
+
+    /// Option taking `key=value:key=value` encoder library parameters.
+    pub fn params_option(&self) -> Option<&'static str> {
+        match self {
+            EncoderKind::Libx264 => Some("-x264-params"),
+            EncoderKind::Libx265 => Some("-x265-params"),
+            EncoderKind::Libsvtav1 => Some("-svtav1-params"),
+            EncoderKind::LibaomAv1 => Some("-aom-params"),
+            _ => None,
+        }
+    }
+
+    /// Checks `gop` against what the encoder can express.
+    fn validate_gop(&self, gop: &GopOptions) -> Result<(), PixyError> {
+        if *gop == GopOptions::default() {
+            return Ok(());
+        }
+        if self.is_intra_only() {
+            return Err(PixyError::InvalidArgument(
+                "ProRes and FFV1 are intra-only and take no GOP settings",
+            ));
+        }
+        if gop.keyint == Some(0)
+            || gop
+                .min_keyint
+                .zip(gop.keyint)
+                .is_some_and(|(min, max)| min > max)
+        {
+            return Err(PixyError::InvalidArgument(
+                "keyint must be above 0 and at least min-keyint",
+            ));
+        }
+        // AV1 and VP9 use hierarchical references instead of B-frames.
+        if gop.bframes.is_some()
+            && matches!(
+                self,
+                EncoderKind::Libsvtav1
+                    | EncoderKind::LibaomAv1
+                    | EncoderKind::LibvpxVp9
+                    | EncoderKind::Av1Amf
+            )
+        {
+            return Err(PixyError::InvalidArgument(
+                "this encoder has no B-frame setting",
+            ));
+        }
+        if gop.lookahead.is_some() && matches!(self.family(), Family::Amf | Family::Vaapi) {
+            return Err(PixyError::InvalidArgument(
+                "AMF and VAAPI encoders have no lookahead setting",
+            ));
+        }
+        Ok(())
+    }
+
+    /// ffmpeg arguments for `gop`; lookahead for x265 and SVT-AV1 goes through
+    /// `codec_params` instead.
+    fn gop_args(&self, gop: &GopOptions) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut push = |k: &str, v: u32| {
+            args.push(k.to_string());
+            args.push(v.to_string());
+        };
+        if let Some(keyint) = gop.keyint {
+            push("-g", keyint);
+        }
+        if let Some(min) = gop.min_keyint {
+            push("-keyint_min", min);
+        }
+        if let Some(bframes) = gop.bframes {
+            push("-bf", bframes);
+        }
+        if let Some(lookahead) = gop.lookahead {
+            match self.family() {
+                Family::Qsv => push("-look_ahead_depth", lookahead),
+                Family::Nvenc => push("-rc-lookahead", lookahead),
+                _ => match self {
+                    EncoderKind::Libx264 => push("-rc-lookahead", lookahead),
+                    EncoderKind::LibaomAv1 | EncoderKind::LibvpxVp9 => {
+                        push("-lag-in-frames", lookahead)
+                    }
+                    _ => {}
+                },
+            }
+        }
+        args
+    }
+
+    /// Pixel formats the encoder accepts; the first is the 8-bit default (ProRes has
+    /// no 8-bit mode). VAAPI and QSV formats name the upload format.
+    pub fn supported_pix_fmts(&self) -> &'static [&'static str] {
+        match self {
+            EncoderKind::Libx264 => &[
+                "yuv420p",
+                "yuv422p",
+                "yuv444p",
+                "nv12",
+                "yuv420p10le",
+                "yuv422p10le",
+                "yuv444p10le",
+            ],
+            EncoderKind::Libx265 => &[
+                "yuv420p",
+                "yuv422p",
+                "yuv444p",
+                "yuv420p10le",
+                "yuv422p10le",
+                "yuv444p10le",
+                "yuv420p12le",
+                "yuv422p12le",
+                "yuv444p12le",
+            ],
+            EncoderKind::Libsvtav1 => &["yuv420p", "yuv420p10le"],
+            EncoderKind::LibaomAv1 => &[
+                "yuv420p",
+                "yuv422p",
+                "yuv444p",
+                "yuv420p10le",
+                "yuv422p10le",
+                "yuv444p10le",
+            ],
+            EncoderKind::LibvpxVp9 => &[
+                "yuv420p",
+                "yuva420p",
+                "yuv422p",
+                "yuv444p",
+                "yuv420p10le",
+                "yuv422p10le",
+                "yuv444p10le",
+            ],
+            EncoderKind::ProresKs => &["yuv422p10le", "yuv444p10le", "yuva444p10le"],
+            EncoderKind::Ffv1 => &[
+                "yuv420p",
+                "yuv422p",
+                "yuv444p",
+                "yuva444p",
+                "yuv420p10le",
+                "yuv444p10le",
+                "yuv444p16le",
+                "yuva444p16le",
+            ],
+            EncoderKind::H264Nvenc => &["yuv420p", "nv12", "yuv444p"],
+            EncoderKind::HevcNvenc => &["yuv420p", "nv12", "yuv444p", "p010le", "yuv444p16le"],
+            EncoderKind::Av1Nvenc | EncoderKind::HevcAmf | EncoderKind::Av1Amf => {
+                &["yuv420p", "nv12", "p010le"]
+            }
+            EncoderKind::H264Amf | EncoderKind::H264Qsv | EncoderKind::H264Vaapi => {
+                &["yuv420p", "nv12"]
+            }
+            EncoderKind::HevcQsv
+            | EncoderKind::Av1Qsv
+            | EncoderKind::HevcVaapi
+            | EncoderKind::Av1Vaapi => &["yuv420p", "nv12", "yuv420p10le", "p010le"],
+        }
+    }
+
+    /// Default pixel format for a high bit depth encode; `None` for the 8-bit-only
+    /// hardware H.264 encoders.
+    pub fn high_bit_depth_pix_fmt(&self) -> Option<&'static str> {
+        match self {
+            EncoderKind::H264Nvenc
+            | EncoderKind::H264Amf
+            | EncoderKind::H264Qsv
+            | EncoderKind::H264Vaapi => None,
+            EncoderKind::ProresKs => Some("yuv422p10le"),
+            EncoderKind::Ffv1 => Some("yuv444p16le"),
+            _ if self.is_hardware() => Some("p010le"),
+            _ => Some("yuv420p10le"),
+        }
+    }
+
+    /// Pixel format carrying alpha for encoders that can store it.
+    pub fn alpha_pix_fmt(&self) -> Option<&'static str> {
+        match self {
+            EncoderKind::ProresKs => Some("yuva444p10le"),
+            EncoderKind::LibvpxVp9 => Some("yuva420p"),
+            EncoderKind::Ffv1 => Some("yuva444p"),
+            _ => None,
+        }
+    }
 }
 
+/// Share of a target size left for container overhead.
+const MUX_OVERHEAD: f64 = 0.02;
+
+/// DRM render node VAAPI and QSV encode on when none is configured.
+pub const DEFAULT_RENDER_NODE: &str = "/dev/dri/renderD128";
+
 /// Encoding options exposed to users, mapped to ffmpeg arguments.
 #[derive(Debug, Clone)]
 pub struct EncoderOptions {
     pub encoder: EncoderKind,
     pub preset: Option<String>,
     pub tune: Option<String>,
-    pub crf: Option<u8>,
+    pub rate_control: Option<RateControl>,
     pub pix_fmt: Option<String>,
-    pub container: Option<String>,
+    /// Render node for VAAPI and QSV on Linux; `DEFAULT_RENDER_NODE` when `None`.
+    pub render_node: Option<PathBuf>,
+    pub gop: GopOptions,
+    /// Library parameters passed through `EncoderKind::params_option`, e.g.
+    /// `aq-mode=3` for x265. They override the ones the other options derive.
+    pub codec_params: BTreeMap<String, String>,
+    /// Extra ffmpeg output options appended after the encoder's own; may not touch
+    /// inputs, stream mapping, codecs, filters or the output format.
+    pub extra_args: Vec<String>,
 }
 
 impl EncoderOptions {
-    /// Converts options to ffmpeg `-c:v`, `-preset`, `-tune`, `-crf`, `-pix_fmt` args.
+    /// Options for `encoder` with its defaults for everything else.
+    pub fn new(encoder: EncoderKind) -> Self {
+        Self {
+            encoder,
+            preset: None,
+            tune: None,
+            rate_control: None,
+            pix_fmt: None,
+            render_node: None,
+            gop: GopOptions::default(),
+            codec_params: BTreeMap::new(),
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Checks the preset, GOP, passthrough args, pixel format and rate control against the encoder's legal values.
+    pub fn validate(&self) -> Result<(), PixyError> {
+        if let Some(preset) = &self.preset {
+            self.encoder.validate_preset(preset)?;
+        }
+        self.encoder.validate_gop(&self.gop)?;
+        if !self.codec_params.is_empty() {
+            if self.encoder.params_option().is_none() {
+                return Err(PixyError::InvalidArgument(
+                    "codec params need libx264, libx265, libsvtav1 or libaom-av1",
+                ));
+            }
+            let bad = |s: &str| s.is_empty() || s.contains([':', '=']);
+            if self
+                .codec_params
+                .iter()
+                .any(|(k, v)| bad(k) || v.contains(':'))
+            {
+                return Err(PixyError::InvalidArgument(
+                    "codec params need non-empty keys without `:` or `=` and values without `:`",
+                ));
+            }
+        }
+        for arg in &self.extra_args {
+            // Negative numbers are values, not options.
+            let Some(name) = arg.strip_prefix('-').filter(|n| n.parse::<f64>().is_err()) else {
+                continue;
+            };
+            let name = format!("-{}", name.split(':').next().unwrap_or_default());
+            if RESERVED_ARGS.contains(&name.as_str()) {
+                return Err(PixyError::InvalidArgument(
+                    "extra ffmpeg args may not set inputs, stream mapping, codecs, codec params, filters, passes, pixel format or output format",
+                ));
+            }
+        }
+        if let Some(pix_fmt) = &self.pix_fmt {
+            if !self
+                .encoder
+                .supported_pix_fmts()
+                .contains(&pix_fmt.as_str())
+            {
+                return Err(PixyError::InvalidArgument(
+                    "pixel format not supported by this encoder (hardware H.264 is 8-bit only; ProRes needs 4:2:2 or 4:4:4 10-bit)",
+                ));
+            }
+        }
+        match self.rate_control {
+            None => Ok(()),
+            Some(RateControl::Lossless) if self.encoder.supports_lossless() => Ok(()),
+            Some(RateControl::Lossless) => Err(PixyError::InvalidArgument(
+                "lossless needs libx264, libx265, libvpx-vp9 or ffv1",
+            )),
+            Some(_) if self.encoder == EncoderKind::Ffv1 => Err(PixyError::InvalidArgument(
+                "FFV1 is always lossless and takes no quality or bitrate",
+            )),
+            Some(RateControl::ConstantQuality(q)) => {
+                if self
+                    .encoder
+                    .quality_range()
+                    .is_some_and(|range| range.contains(&q))
+                {
+                    Ok(())
+                } else {
+                    Err(PixyError::InvalidArgument(
+                        "quality is out of range for this encoder (0-51 for H.264/HEVC, 1-51 on QSV, 0-63 for AV1 and VP9, 0-255 for AV1 on AMF/VAAPI, 0-32 for ProRes)",
+                    ))
+                }
+            }
+            Some(_) if !self.encoder.supports_bitrate() => Err(PixyError::InvalidArgument(
+                "ProRes has no bitrate control; use a constant quality",
+            )),
+            Some(RateControl::Cbr { bitrate } | RateControl::TargetBitrate(bitrate))
+                if bitrate == 0 =>
+            {
+                Err(PixyError::InvalidArgument("bitrate must be above 0"))
+            }
+            Some(RateControl::Vbr {
+                bitrate,
+                max_bitrate,
+            }) if bitrate == 0 || max_bitrate < bitrate => Err(PixyError::InvalidArgument(
+                "VBR needs a bitrate above 0 and a max bitrate at least as high",
+            )),
+            Some(RateControl::TargetSize { bytes: 0 }) => {
+                Err(PixyError::InvalidArgument("target size must be above 0"))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Turns `RateControl::TargetSize` into the average video bitrate that fills it over
+    /// `duration` seconds next to `audio_bps` of copied audio; other modes are kept.
+    pub fn resolve_target_size(
+        &self,
+        duration: f64,
+        audio_bps: u64,
+    ) -> Result<EncoderOptions, PixyError> {
+        let Some(RateControl::TargetSize { bytes }) = self.rate_control else {
+            return Ok(self.clone());
+        };
+        if duration <= 0.0 {
+            return Err(PixyError::InvalidArgument(
+                "target size needs a known duration",
+            ));
+        }
+        let total_bps = bytes as f64 * 8.0 * (1.0 - MUX_OVERHEAD) / duration;
+        let video_kbps = (total_bps - audio_bps as f64) / 1000.0;
+        if video_kbps < 1.0 {
+            return Err(PixyError::InvalidArgument(
+                "target size is too small for the duration and copied audio",
+            ));
+        }
+        Ok(EncoderOptions {
+            rate_control: Some(RateControl::TargetBitrate(
+                video_kbps.min(u32::MAX as f64) as u32
+            )),
+            ..self.clone()
+        })
+    }
+
+    /// Whether encoding takes two ffmpeg runs; see `pass_ffmpeg_args`.
+    pub fn is_two_pass(&self) -> bool {
+        matches!(self.rate_control, Some(RateControl::TargetBitrate(_)))
+            && self.encoder.supports_two_pass()
+    }
+
+    /// Global ffmpeg args creating the hardware device VAAPI and QSV encode on; they
+    /// must precede the first input.
+    /// Why: Without a device, `hwupload` has nothing to upload to and the encoder
+    /// rejects software frames.
+    pub fn hw_device_args(&self) -> Vec<String> {
+        let node = self
+            .render_node
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_RENDER_NODE))
+            .to_string_lossy();
+        let devices = match self.encoder.family() {
+            Family::Vaapi => vec![format!("vaapi=hw:{node}")],
+            // On Linux QSV sits on top of VAAPI, which is what picks the render node.
+            Family::Qsv if cfg!(target_os = "linux") => {
+                vec![format!("vaapi=va:{node}"), "qsv=hw@va".into()]
+            }
+            Family::Qsv => vec!["qsv=hw".into()],
+            _ => return Vec::new(),
+        };
+        let mut args = Vec::new();
+        for device in devices {
+            args.extend(["-init_hw_device".into(), device]);
+        }
+        args.extend(["-filter_hw_device".into(), "hw".into()]);
+        args
+    }
+
+    /// Filters turning software frames into the encoder's hardware surfaces, to run
+    /// after every software filter. The upload format takes the place of `-pix_fmt`.
+    pub fn hw_upload_filter(&self) -> Option<String> {
+        let format = match self.pix_fmt.as_deref() {
+            Some("yuv420p10le" | "p010le") => "p010le",
+            _ => "nv12",
+        };
+        match self.encoder.family() {
+            Family::Vaapi => Some(format!("format={format},hwupload")),
+            Family::Qsv => Some(format!(
+                "format={format},hwupload=extra_hw_frames=64,format=qsv"
+            )),
+            _ => None,
+        }
+    }
+
+    /// Converts options to ffmpeg `-c:v`, `-preset`, `-tune`, rate control and
+    /// `-pix_fmt` args.
     /// Why: Encoders differ, but we expose a consistent API surface to users.
     pub fn to_ffmpeg_args(&self) -> Vec<String> {
+        self.build_args(None)
+    }
+
+    /// `to_ffmpeg_args` for pass 1 or 2 of a two-pass encode, sharing the statistics
+    /// file prefix `stats`.
+    pub fn pass_ffmpeg_args(&self, pass: u8, stats: &Path) -> Vec<String> {
+        self.build_args(Some((pass, stats)))
+    }
+
+    fn build_args(&self, pass: Option<(u8, &Path)>) -> Vec<String> {
         let mut args = Vec::new();
         args.push("-c:v".into());
-        args.push(
-            match self.encoder {
-                EncoderKind::H264Nvenc => "h264_nvenc",
-                EncoderKind::HevcNvenc => "hevc_nvenc",
-                EncoderKind::H264Amf => "h264_amf",
-                EncoderKind::HevcAmf => "hevc_amf",
-                EncoderKind::H264Qsv => "h264_qsv",
-                EncoderKind::HevcQsv => "hevc_qsv",
-                EncoderKind::H264Vaapi => "h264_vaapi",
-                EncoderKind::HevcVaapi => "hevc_vaapi",
-                EncoderKind::Libx264 => "libx264",
-                EncoderKind::Libx265 => "libx265",
-            }
-            .into(),
-        );
+        args.push(self.encoder.ffmpeg_name().into());
 
         if let Some(preset) = &self.preset {
-            args.push("-preset".into());
-            args.push(preset.clone());
+            args.extend(self.encoder.preset_args(preset));
         }
         if let Some(tune) = &self.tune {
             args.push("-tune".into());
             args.push(tune.clone());
         }
-        if let Some(crf) = self.crf {
-            args.push("-crf".into());
-            args.push(crf.to_string());
+        if let Some(rc) = self.rate_control {
+            args.extend(self.encoder.rate_control_args(rc));
         }
-        if let Some(pix_fmt) = &self.pix_fmt {
+        args.extend(self.encoder.gop_args(&self.gop));
+        // x265 takes its pass through `-x265-params`; see `library_params`.
+        if let Some((pass, stats)) = pass.filter(|_| self.encoder != EncoderKind::Libx265) {
+            args.extend([
+                "-pass".into(),
+                pass.to_string(),
+                "-passlogfile".into(),
+                stats.to_string_lossy().into_owned(),
+            ]);
+        }
+        match self.encoder {
+            EncoderKind::ProresKs => {
+                let alpha = self
+                    .pix_fmt
+                    .as_deref()
+                    .is_some_and(|f| f.starts_with("yuva"));
+                // Only the 4444 profiles store alpha.
+                let profile = match self.preset.as_deref() {
+                    Some(p @ ("4444" | "4444xq")) => p,
+                    _ if alpha => "4444",
+                    Some(p) => p,
+                    None => "hq",
+                };
+                args.extend(["-profile:v".into(), profile.into()]);
+            }
+            // Archive settings: intra-only, level 3 with per-slice CRCs for integrity checks.
+            EncoderKind::Ffv1 => args.extend(
+                ["-level", "3", "-g", "1", "-slices", "16", "-slicecrc", "1"].map(String::from),
+            ),
+            _ => {}
+        }
+        if let Some(pix_fmt) = self
+            .pix_fmt
+            .as_ref()
+            .filter(|_| !self.encoder.uses_hw_upload())
+        {
             args.push("-pix_fmt".into());
             args.push(pix_fmt.clone());
         }
+        if let Some(option) = self.encoder.params_option() {
+            let params = self.library_params(pass);
+            if !params.is_empty() {
+                let joined: Vec<String> = params.iter().map(|(k, v)| format!("{k}={v}")).collect();
+                args.extend([option.into(), joined.join(":")]);
+            }
+        }
+        args.extend(self.extra_args.iter().cloned());
         args
     }
+
+    /// Library params derived from the other options, overridden by `codec_params`.
+    /// Why: ffmpeg keeps only the last `-x265-params`, so everything has to be merged
+    /// into one.
+    fn library_params(&self, pass: Option<(u8, &Path)>) -> BTreeMap<String, String> {
+        let mut params = BTreeMap::new();
+        if let Some((pass, stats)) = pass.filter(|_| self.encoder == EncoderKind::Libx265) {
+            // `:` separates params and `\` escapes, so both need escaping in the path.
+            let stats = stats
+                .to_string_lossy()
+                .replace('\\', "\\\\")
+                .replace(':', "\\:");
+            params.insert("pass".into(), pass.to_string());
+            params.insert("stats".into(), stats);
+        }
+        if self.encoder == EncoderKind::Libx265 && self.rate_control == Some(RateControl::Lossless)
+        {
+            params.insert("lossless".to_string(), "1".to_string());
+        }
+        if let Some(lookahead) = self.gop.lookahead {
+            match self.encoder {
+                EncoderKind::Libx265 => {
+                    params.insert("rc-lookahead".into(), lookahead.to_string());
+                }
+                EncoderKind::Libsvtav1 => {
+                    params.insert("lookahead".into(), lookahead.to_string());
+                }
+                _ => {}
+            }
+        }
+        params.extend(self.codec_params.clone());
+        params
+    }
+
+    /// Options that keep transparency when muxing into `container`: the same encoder with
+    /// an alpha pixel format if it has one, otherwise VP9 for WebM/Matroska or ProRes
+    /// 4444 for QuickTime. `None` when the container cannot carry alpha video, or is
+    /// unknown (`None`) and the encoder has no alpha format. A switched encoder gets the
+    /// quality clamped to its own scale, and no rate control where it has no equivalent.
+    /// Why: Hardware H.264/HEVC encoders have no alpha, and `yuv420p` silently drops it.
+    pub fn with_alpha(&self, container: Option<Container>) -> Option<EncoderOptions> {
+        if let Some(mut pix_fmt) = self.encoder.alpha_pix_fmt() {
+            // FFV1 is the one alpha encoder that keeps more than 10 bits.
+            if self.encoder == EncoderKind::Ffv1
+                && self.pix_fmt.as_deref().is_some_and(|f| f.contains("p1"))
+            {
+                pix_fmt = "yuva444p16le";
+            }
+            return Some(EncoderOptions {
+                pix_fmt: Some(pix_fmt.into()),
+                ..self.clone()
+            });
+        }
+        let encoder = match container? {
+            Container::WebM | Container::Mkv => EncoderKind::LibvpxVp9,
+            Container::Mov => EncoderKind::ProresKs,
+            Container::Mp4 | Container::Nut => return None,
+        };
+        let rate_control = match self.rate_control {
+            Some(RateControl::ConstantQuality(q)) => encoder
+                .quality_range()
+                .map(|range| RateControl::ConstantQuality(q.clamp(*range.start(), *range.end()))),
+            Some(RateControl::Lossless) if !encoder.supports_lossless() => None,
+            Some(_) if !encoder.supports_bitrate() => None,
+            rc => rc,
+        };
+        Some(EncoderOptions {
+            pix_fmt: encoder.alpha_pix_fmt().map(Into::into),
+            encoder,
+            // Presets and tunes of the original encoder mean nothing to the new one.
+            preset: None,
+            tune: None,
+            rate_control,
+            render_node: None,
+            // GOP settings and library params are encoder-specific (ProRes has neither).
+            gop: GopOptions::default(),
+            codec_params: BTreeMap::new(),
+            extra_args: self.extra_args.clone(),
+        })
+    }
+}
+
+/// Encoder picked by `auto_select_encoder`, with a human-readable explanation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncoderChoice {
+    pub encoder: EncoderKind,
+    pub reason: String,
+}
+
+/// Picks the hardware encoder matching `device`'s vendor (NVENC on NVIDIA, AMF then
+/// VAAPI on AMD, QSV then VAAPI on Intel), falling back to libx265 and libx264 when
+/// none of them is built into ffmpeg or passes a test encode. VAAPI and QSV are tried
+/// on `render_node` (`DEFAULT_RENDER_NODE` when `None`).
+pub fn auto_select_encoder(
+    device: Option<&VulkanDevice>,
+    render_node: Option<&Path>,
+) -> Result<EncoderChoice, PixyError> {
+    let vendor = device.and_then(VulkanDevice::vendor);
+    let hardware: &[EncoderKind] = match vendor {
+        Some(GpuVendor::Nvidia) => &[EncoderKind::HevcNvenc, EncoderKind::H264Nvenc],
+        Some(GpuVendor::Amd) => &[
+            EncoderKind::HevcAmf,
+            EncoderKind::H264Amf,
+            EncoderKind::HevcVaapi,
+            EncoderKind::H264Vaapi,
+        ],
+        Some(GpuVendor::Intel) => &[
+            EncoderKind::HevcQsv,
+            EncoderKind::H264Qsv,
+            EncoderKind::HevcVaapi,
+            EncoderKind::H264Vaapi,
+        ],
+        None => &[],
+    };
+    let source = match (device, vendor) {
+        (Some(d), Some(v)) => format!("{} GPU {} ({})", v.name(), d.index, d.name),
+        (Some(d), None) => format!(
+            "GPU {} ({}) has no supported hardware encoder",
+            d.index, d.name
+        ),
+        (None, _) => "no GPU selected".to_string(),
+    };
+
+    let listed = list_ffmpeg_encoders()?;
+    let mut skipped = Vec::new();
+    for kind in hardware
+        .iter()
+        .chain(&[EncoderKind::Libx265, EncoderKind::Libx264])
+    {
+        let usable = if !listed.iter().any(|name| name == kind.ffmpeg_name()) {
+            Err(format!("{} is not built into ffmpeg", kind.ffmpeg_name()))
+        } else if kind.is_hardware() {
+            test_encode(&EncoderOptions {
+                render_node: render_node.map(Path::to_path_buf),
+                ..EncoderOptions::new(*kind)
+            })
+            .map_err(|e| match e {
+                PixyError::EncoderUnavailable { reason, .. } => {
+                    format!("{} failed a test encode: {reason}", kind.ffmpeg_name())
+                }
+                other => format!("{}: {other}", kind.ffmpeg_name()),
+            })
+        } else {
+            Ok(())
+        };
+        match usable {
+            Ok(()) => {
+                let mut reason = format!("{source}: using {}", kind.ffmpeg_name());
+                if !skipped.is_empty() {
+                    reason.push_str(&format!(" ({})", skipped.join("; ")));
+                }
+                return Ok(EncoderChoice {
+                    encoder: *kind,
+                    reason,
+                });
+            }
+            Err(why) => skipped.push(why),
+        }
+    }
+    Err(PixyError::EncoderUnavailable {
+        encoder: "auto",
+        reason: skipped.join("; "),
+    })
+}
+
+/// Encoders from `EncoderKind::ALL` that the resolved ffmpeg can actually use, in
+/// order of preference.
+/// Why: Builds differ in what they were compiled with, and a hardware encoder can be
+/// listed by `ffmpeg -encoders` yet fail without the matching GPU or driver.
+pub fn available_encoders() -> Result<Vec<EncoderKind>, PixyError> {
+    let listed = list_ffmpeg_encoders()?;
+    Ok(EncoderKind::ALL
+        .into_iter()
+        .filter(|kind| listed.iter().any(|name| name == kind.ffmpeg_name()))
+        .filter(|kind| !kind.is_hardware() || test_encode(&EncoderOptions::new(*kind)).is_ok())
+        .collect())
+}
+
+/// Fails with `EncoderUnavailable` unless the resolved ffmpeg can encode with `options`.
+/// Why: The pipeline checks this before extraction so a missing encoder does not
+/// surface only after hours of upscaling.
+pub fn check_encoder(options: &EncoderOptions) -> Result<(), PixyError> {
+    let kind = options.encoder;
+    if !list_ffmpeg_encoders()?
+        .iter()
+        .any(|name| name == kind.ffmpeg_name())
+    {
+        return Err(PixyError::EncoderUnavailable {
+            encoder: kind.ffmpeg_name(),
+            reason: "not built into this ffmpeg".into(),
+        });
+    }
+    if kind.is_hardware() {
+        test_encode(options)?;
+    }
+    Ok(())
+}
+
+/// Names of the video encoders listed by `ffmpeg -encoders`.
+fn list_ffmpeg_encoders() -> Result<Vec<String>, PixyError> {
+    let ffmpeg = resolve_tool("ffmpeg")?;
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-encoders"])
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(PixyError::ProcessFailed {
+            cmd: "ffmpeg -hide_banner -encoders".into(),
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(parse_encoder_list(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the table after the `------` separator: capability flags, then the name.
+fn parse_encoder_list(text: &str) -> Vec<String> {
+    text.lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let flags = fields.next()?;
+            let name = fields.next()?;
+            flags.starts_with('V').then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Encodes one small black frame to the null muxer, with the same hardware device
+/// setup as the real encode.
+fn test_encode(options: &EncoderOptions) -> Result<(), PixyError> {
+    let mut args: Vec<String> = vec!["-hide_banner".into(), "-loglevel".into(), "error".into()];
+    args.extend(options.hw_device_args());
+    args.extend([
+        "-f".into(),
+        "lavfi".into(),
+        "-i".into(),
+        // Large enough for NVENC's minimum frame size.
+        "color=c=black:s=256x256:d=0.1".into(),
+        "-frames:v".into(),
+        "1".into(),
+    ]);
+    if let Some(upload) = options.hw_upload_filter() {
+        args.extend(["-vf".into(), upload]);
+    }
+    args.extend([
+        "-c:v".into(),
+        options.encoder.ffmpeg_name().into(),
+        "-f".into(),
+        "null".into(),
+        "-".into(),
+    ]);
+    let ffmpeg = resolve_tool("ffmpeg")?;
+    let output = Command::new(ffmpeg)
+        .args(&args)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(PixyError::EncoderUnavailable {
+            encoder: options.encoder.ffmpeg_name(),
+            reason: stderr
+                .lines()
+                .rev()
+                .find(|l| !l.trim().is_empty())
+                .unwrap_or("test encode failed")
+                .trim()
+                .to_string(),
+        });
+    }
+    Ok(())
 }
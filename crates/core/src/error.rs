@@ -2,6 +2,8 @@ use std::io;
 
 use thiserror::Error;
 
+use crate::upscalers::UpscalerFailure;
+
 #[derive(Debug, Error)]
 pub enum PixyError {
     #[error("Command not found: {0}")]
@@ -13,9 +15,21 @@ pub enum PixyError {
     #[error("JSON parse error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("TOML parse error: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Regex error: {0}")]
+    Regex(#[from] regex::Error),
+
     #[error("Invalid argument: {0}")]
     InvalidArgument(&'static str),
 
+    #[error("Encoder {encoder} is not usable with this ffmpeg: {reason}")]
+    EncoderUnavailable {
+        encoder: &'static str,
+        reason: String,
+    },
+
     #[error("Process failed: {cmd} (code {code:?})\n{stderr}")]
     ProcessFailed {
         cmd: String,
@@ -23,3 +37,13 @@ pub enum PixyError {
         stderr: String,
     },
 }
+
+impl PixyError {
+    /// Classifies a failed upscaler run from the log tail captured in `ProcessFailed`.
+    pub fn upscaler_failure(&self) -> Option<UpscalerFailure> {
+        match self {
+            PixyError::ProcessFailed { stderr, .. } => UpscalerFailure::classify(stderr),
+            _ => None,
+        }
+    }
+}
@@ -1,5 +1,6 @@
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 use crate::error::PixyError;
 use crate::paths::resolve_tool;
@@ -29,6 +30,12 @@ impl Prefilter {
 pub struct FrameExtractOptions {
     pub prefilter: Prefilter,
     pub frame_format: String, // png|webp|bmp
+    /// Extract RGBA frames; forces PNG.
+    pub alpha: bool,
+    /// Extract 16-bit per channel frames for a 10-bit or deeper encode; forces PNG.
+    pub high_bit_depth: bool,
+    /// Input decoder override, e.g. `libvpx-vp9`, whose alpha ffmpeg's native decoder drops.
+    pub decoder: Option<String>,
 }
 
 impl Default for FrameExtractOptions {
@@ -36,28 +43,49 @@ impl Default for FrameExtractOptions {
         Self {
             prefilter: Prefilter::None,
             frame_format: "png".into(),
+            alpha: false,
+            high_bit_depth: false,
+            decoder: None,
         }
     }
 }
 
 /// Extracts frames from the input using ffmpeg to an output directory.
-/// Why: Upscaler binaries operate on image sequences; we preserve PTS for sync.
+/// Why: Upscaler binaries operate on image sequences; frames are numbered sequentially
+/// from 1 so later stages can read them back at a constant rate.
 pub fn extract_frames(
     input: &Path,
     out_dir: &Path,
     opts: &FrameExtractOptions,
 ) -> Result<PathBuf, PixyError> {
     std::fs::create_dir_all(out_dir)?;
-    let pattern = out_dir.join(format!("%08d.{}", opts.frame_format));
-    let mut args = vec![
-        "-y".into(),
+    let format = if opts.alpha || opts.high_bit_depth {
+        "png"
+    } else {
+        &opts.frame_format
+    };
+    let pattern = out_dir.join(format!("%08d.{format}"));
+    let mut args = vec!["-y".into()];
+    if let Some(decoder) = &opts.decoder {
+        args.push("-c:v".into());
+        args.push(decoder.clone());
+    }
+    args.extend([
         "-i".into(),
         input.to_string_lossy().to_string(),
         "-vsync".into(),
         "0".into(),
-        "-frame_pts".into(),
-        "1".into(),
-    ];
+    ]);
+    let pix_fmt = match (opts.alpha, opts.high_bit_depth) {
+        (true, true) => Some("rgba64be"),
+        (true, false) => Some("rgba"),
+        (false, true) => Some("rgb48be"),
+        (false, false) => None,
+    };
+    if let Some(pix_fmt) = pix_fmt {
+        args.push("-pix_fmt".into());
+        args.push(pix_fmt.into());
+    }
 
     if let Some(f) = opts.prefilter.filter_str() {
         args.push("-vf".into());
@@ -66,14 +94,44 @@ pub fn extract_frames(
 
     args.push(pattern.to_string_lossy().to_string());
 
+    run_ffmpeg(&args)?;
+    Ok(pattern)
+}
+
+/// How much of ffmpeg's stderr is kept for error reports.
+const STDERR_TAIL_BYTES: usize = 8 * 1024;
+
+/// Runs ffmpeg with `args`, inheriting stdout. stderr is passed through as it arrives,
+/// so the progress line stays visible, and its tail ends up in `ProcessFailed`.
+pub(crate) fn run_ffmpeg(args: &[String]) -> Result<(), PixyError> {
     let ffmpeg = resolve_tool("ffmpeg")?;
-    let status = Command::new(ffmpeg).args(args.clone()).status()?;
+    let mut child = Command::new(ffmpeg)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut tail = Vec::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = stderr.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let _ = std::io::stderr().write_all(&buf[..n]);
+            tail.extend_from_slice(&buf[..n]);
+            if tail.len() > 2 * STDERR_TAIL_BYTES {
+                tail.drain(..tail.len() - STDERR_TAIL_BYTES);
+            }
+        }
+    }
+    let status = child.wait()?;
     if !status.success() {
+        let start = tail.len().saturating_sub(STDERR_TAIL_BYTES);
         return Err(PixyError::ProcessFailed {
             cmd: format!("ffmpeg {:?}", args),
             code: status.code(),
-            stderr: String::new(),
+            stderr: String::from_utf8_lossy(&tail[start..]).trim().to_string(),
         });
     }
-    Ok(pattern)
+    Ok(())
 }
@@ -0,0 +1,213 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::dedup::link_or_copy;
+use crate::error::PixyError;
+use crate::frames::run_ffmpeg;
+use crate::upscalers::cpu::image_error;
+
+/// Extensions treated as still images rather than video containers.
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "webp", "bmp", "tif", "tiff", "exr", "tga", "dpx",
+];
+
+/// Extensions the `image` crate reads and writes here; others go through ffmpeg.
+const NATIVE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp"];
+
+/// Whether `path` names a still image by its extension.
+pub fn is_image_path(path: &Path) -> bool {
+    has_extension(path, IMAGE_EXTENSIONS)
+}
+
+/// Whether `path` is a printf-style sequence pattern such as `frames/%04d.png`.
+pub fn is_sequence_pattern(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| pattern_regex(&name.to_string_lossy()).is_some())
+}
+
+/// Whether the pipeline should write images to `output` instead of encoding a video:
+/// an existing directory, a path ending in a separator, a sequence pattern or an
+/// image file name.
+pub fn is_image_output(output: &Path) -> bool {
+    let text = output.to_string_lossy();
+    output.is_dir()
+        || text.ends_with('/')
+        || text.ends_with(std::path::MAIN_SEPARATOR)
+        || is_sequence_pattern(output)
+        || is_image_path(output)
+}
+
+/// Resolves an image input: a single image, a directory of images or a printf-style
+/// pattern. Returns `None` for anything else, which is handled as a video.
+pub fn collect_input_images(input: &Path) -> Result<Option<Vec<PathBuf>>, PixyError> {
+    let mut images = if input.is_dir() {
+        std::fs::read_dir(input)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && is_image_path(p))
+            .collect::<Vec<_>>()
+    } else if is_sequence_pattern(input) {
+        let name = input.file_name().unwrap_or_default().to_string_lossy();
+        let re = pattern_regex(&name).expect("checked by is_sequence_pattern");
+        let dir = match input.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut numbered: Vec<(u64, PathBuf)> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter_map(|p| {
+                let name = p.file_name()?.to_string_lossy().into_owned();
+                let n = re.captures(&name)?.get(1)?.as_str().parse().ok()?;
+                Some((n, p))
+            })
+            .collect();
+        numbered.sort();
+        return non_empty(numbered.into_iter().map(|(_, p)| p).collect()).map(Some);
+    } else if is_image_path(input) {
+        vec![input.to_path_buf()]
+    } else {
+        return Ok(None);
+    };
+    images.sort();
+    non_empty(images).map(Some)
+}
+
+/// Copies the input images into `frames_dir` as a PNG sequence numbered from 1, the
+/// layout `extract_frames` produces, and returns its pattern.
+/// Why: Every later stage (dedup, scenes, interpolation) then works the same for
+/// images and video; formats the upscalers cannot read (EXR, TIFF) are converted.
+pub fn stage_input_images(images: &[PathBuf], frames_dir: &Path) -> Result<PathBuf, PixyError> {
+    std::fs::create_dir_all(frames_dir)?;
+    for (i, image) in images.iter().enumerate() {
+        let staged = frames_dir.join(format!("{:08}.png", i + 1));
+        if has_extension(image, &["png"]) {
+            link_or_copy(image, &staged)?;
+        } else {
+            convert_image(image, &staged)?;
+        }
+    }
+    Ok(frames_dir.join("%08d.png"))
+}
+
+/// Writes the final frames to an image `output`: a single file, a printf-style pattern,
+/// or a directory. In a directory, frames keep the names of `source_names` when given
+/// (image inputs) and are numbered otherwise.
+pub fn write_image_output(
+    frames: &[PathBuf],
+    source_names: Option<&[PathBuf]>,
+    output: &Path,
+) -> Result<(), PixyError> {
+    if is_sequence_pattern(output) {
+        if let Some(dir) = output.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let pattern = output.to_string_lossy();
+        for (i, frame) in frames.iter().enumerate() {
+            let target = PathBuf::from(format_pattern(&pattern, i + 1));
+            deliver(frame, &target)?;
+        }
+        return Ok(());
+    }
+    if is_image_path(output) && !output.is_dir() {
+        let [frame] = frames else {
+            return Err(PixyError::InvalidArgument(
+                "a single image output needs a single input frame; use a directory or pattern",
+            ));
+        };
+        if let Some(dir) = output.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        return deliver(frame, output);
+    }
+
+    std::fs::create_dir_all(output)?;
+    for (i, frame) in frames.iter().enumerate() {
+        let stem = match source_names.and_then(|names| names.get(i)) {
+            Some(name) => name.file_stem().unwrap_or_default().to_os_string(),
+            None => format!("{:08}", i + 1).into(),
+        };
+        let target = output
+            .join(stem)
+            .with_extension(frame.extension().unwrap_or_default());
+        deliver(frame, &target)?;
+    }
+    Ok(())
+}
+
+/// Copies `frame` to `target`, converting when the extensions differ.
+fn deliver(frame: &Path, target: &Path) -> Result<(), PixyError> {
+    let same_format = frame
+        .extension()
+        .zip(target.extension())
+        .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b));
+    if target.exists() {
+        std::fs::remove_file(target)?;
+    }
+    if same_format {
+        std::fs::copy(frame, target)?;
+        Ok(())
+    } else {
+        convert_image(frame, target)
+    }
+}
+
+/// Converts between image formats, using ffmpeg for formats the `image` crate lacks.
+fn convert_image(src: &Path, dst: &Path) -> Result<(), PixyError> {
+    if has_extension(src, NATIVE_EXTENSIONS) && has_extension(dst, NATIVE_EXTENSIONS) {
+        return image::open(src)
+            .map_err(image_error)?
+            .save(dst)
+            .map_err(image_error);
+    }
+    let args = vec![
+        "-y".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-i".into(),
+        src.to_string_lossy().to_string(),
+        "-frames:v".into(),
+        "1".into(),
+        dst.to_string_lossy().to_string(),
+    ];
+    run_ffmpeg(&args)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
+fn non_empty(images: Vec<PathBuf>) -> Result<Vec<PathBuf>, PixyError> {
+    if images.is_empty() {
+        return Err(PixyError::InvalidArgument("no input images found"));
+    }
+    Ok(images)
+}
+
+/// Regex matching file names of a pattern with one `%d`/`%0Nd` field, capturing the number.
+fn pattern_regex(name: &str) -> Option<Regex> {
+    let field = Regex::new(r"%0?\d*d").expect("static regex");
+    let m = field.find(name)?;
+    let source = format!(
+        "^{}(\\d+){}$",
+        regex::escape(&name[..m.start()]),
+        regex::escape(&name[m.end()..])
+    );
+    Regex::new(&source).ok()
+}
+
+/// Substitutes `n` into the first `%d`/`%0Nd` field of `pattern`.
+fn format_pattern(pattern: &str, n: usize) -> String {
+    let field = Regex::new(r"%(0?)(\d*)d").expect("static regex");
+    field
+        .replacen(pattern, 1, |caps: &regex::Captures| {
+            let width: usize = caps[2].parse().unwrap_or(0);
+            if &caps[1] == "0" {
+                format!("{n:0width$}")
+            } else {
+                format!("{n:width$}")
+            }
+        })
+        .into_owned()
+}
@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::PixyError;
+use crate::paths::resolve_tool;
+use crate::probe::FrameRate;
+use crate::upscalers::cpu::{image_error, list_sequence};
+use crate::upscalers::log::{parse_line, run_streaming};
+use crate::upscalers::UpscalerEvent;
+
+/// Where interpolation runs relative to upscaling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterpolationPlacement {
+    /// Interpolate source frames; the upscaler then processes every generated frame.
+    BeforeUpscale,
+    /// Interpolate upscaled frames; only source frames go through the upscaler.
+    AfterUpscale,
+}
+
+/// Desired output rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InterpolationTarget {
+    /// Multiply the source frame rate, e.g. `2` for 24 → 48 fps.
+    Multiplier(u32),
+    /// Retime to an explicit rate, e.g. 60 fps.
+    Fps(FrameRate),
+}
+
+/// Options for the optional `rife-ncnn-vulkan` frame interpolation stage.
+#[derive(Debug, Clone)]
+pub struct InterpolationOptions {
+    pub target: InterpolationTarget,
+    pub placement: InterpolationPlacement,
+    /// RIFE model directory name, e.g. `rife-v4.6`.
+    pub model: String,
+    /// Scene change threshold in `0.0..=1.0`; frames across a cut are duplicated
+    /// instead of blended. `None` disables scene detection.
+    pub scene_threshold: Option<f32>,
+    /// RIFE UHD mode (`-u`), recommended above 1080p.
+    pub uhd: bool,
+}
+
+impl Default for InterpolationOptions {
+    fn default() -> Self {
+        Self {
+            target: InterpolationTarget::Multiplier(2),
+            placement: InterpolationPlacement::AfterUpscale,
+            model: "rife-v4.6".into(),
+            scene_threshold: Some(0.3),
+            uhd: false,
+        }
+    }
+}
+
+impl InterpolationOptions {
+    /// Checks the multiplier and scene threshold.
+    pub fn validate(&self) -> Result<(), PixyError> {
+        if let InterpolationTarget::Multiplier(0) = self.target {
+            return Err(PixyError::InvalidArgument(
+                "interpolation multiplier must be at least 1",
+            ));
+        }
+        if self
+            .scene_threshold
+            .is_some_and(|t| !(0.0..=1.0).contains(&t))
+        {
+            return Err(PixyError::InvalidArgument(
+                "scene threshold must be between 0.0 and 1.0",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Output frame rate for a given source rate.
+    pub fn output_rate(&self, source: FrameRate) -> Result<FrameRate, PixyError> {
+        match self.target {
+            InterpolationTarget::Multiplier(m) => source.times(m),
+            InterpolationTarget::Fps(rate) => Ok(rate),
+        }
+    }
+
+    /// Number of frames RIFE must produce so the clip keeps its duration at the new rate.
+    pub fn output_frames(
+        &self,
+        input_frames: usize,
+        source: FrameRate,
+    ) -> Result<usize, PixyError> {
+        let out = self.output_rate(source)?;
+        let ratio = out.as_f64() / source.as_f64();
+        Ok(((input_frames as f64) * ratio).round().max(1.0) as usize)
+    }
+}
+
+/// Interpolates the sequence at `input_pattern` into `output_dir` with `rife-ncnn-vulkan`
+/// and returns the output pattern and rate.
+/// Why: Blending across a scene cut produces ghosted frames, so frames that straddle a
+/// cut in `scene_cuts` (indices of the first frame of each new scene) are replaced by
+/// the nearest source frame afterwards.
+pub fn interpolate_frames(
+    input_pattern: &Path,
+    output_dir: &Path,
+    source_rate: FrameRate,
+    gpu: usize,
+    opts: &InterpolationOptions,
+    scene_cuts: &[usize],
+    on_event: &mut dyn FnMut(UpscalerEvent),
+) -> Result<(PathBuf, FrameRate), PixyError> {
+    let sources = list_sequence(input_pattern)?;
+    if sources.is_empty() {
+        return Err(PixyError::InvalidArgument("no frames to interpolate"));
+    }
+    opts.validate()?;
+    std::fs::create_dir_all(output_dir)?;
+    let output_pattern = output_dir.join("%08d.png");
+    let total = opts.output_frames(sources.len(), source_rate)?;
+
+    let rife = resolve_tool("rife-ncnn-vulkan")?;
+    let mut cmd = Command::new(rife);
+    cmd.arg("-i")
+        .arg(input_pattern.parent().unwrap_or(Path::new(".")))
+        .arg("-o")
+        .arg(output_dir)
+        .args(["-n", &total.to_string()])
+        .args(["-m", &opts.model])
+        .args(["-g", &gpu.to_string()])
+        .args(["-f", "%08d.png"])
+        .arg("-v");
+    if opts.uhd {
+        cmd.arg("-u");
+    }
+    run_streaming(&mut cmd, &parse_line, on_event)?;
+
+    for (i, (x0, frac)) in (0..total)
+        .map(|i| source_position(i, sources.len(), total))
+        .enumerate()
+    {
+        if frac > 1e-6 && scene_cuts.contains(&(x0 + 1)) {
+            let nearest = if frac < 0.5 { x0 } else { x0 + 1 };
+            let src = &sources[nearest.min(sources.len() - 1)];
+            let dst = output_dir.join(format!("{:08}.png", i + 1));
+            image::open(src)
+                .map_err(image_error)?
+                .save(&dst)
+                .map_err(image_error)?;
+        }
+    }
+
+    Ok((output_pattern, opts.output_rate(source_rate)?))
+}
+
+/// Source position of output frame `index` as (previous source frame, blend fraction),
+/// matching RIFE's even spacing of `total` outputs over `inputs` source frames.
+fn source_position(index: usize, inputs: usize, total: usize) -> (usize, f64) {
+    let t = index as f64 * inputs as f64 / total as f64;
+    let x0 = t.floor();
+    (x0 as usize, t - x0)
+}
@@ -1,19 +1,49 @@
+pub mod alpha;
+pub mod animation;
+pub mod audio;
+pub mod container;
+pub mod dedup;
 pub mod devices;
 pub mod encoder;
 pub mod error;
 pub mod frames;
+pub mod images;
+pub mod interpolate;
 pub mod models;
 pub mod paths;
 pub mod pipeline;
 pub mod probe;
+pub mod scenes;
+pub mod temporal;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod tiles;
 pub mod upscalers;
 
-pub use devices::{detect_vulkan_devices, VulkanDevice};
-pub use encoder::{EncoderKind, EncoderOptions};
+pub use alpha::AlphaMode;
+pub use animation::{AnimationFormat, LoopCount};
+pub use audio::{AudioCodec, AudioOptions, AudioSelection, Loudness};
+pub use container::Container;
+pub use dedup::{DedupOptions, DuplicateMap};
+pub use devices::{detect_vulkan_devices, GpuVendor, VulkanDevice};
+pub use encoder::{
+    auto_select_encoder, available_encoders, EncoderChoice, EncoderKind, EncoderOptions,
+    GopOptions, RateControl,
+};
 pub use error::PixyError;
 pub use frames::{FrameExtractOptions, Prefilter};
+pub use interpolate::{InterpolationOptions, InterpolationPlacement, InterpolationTarget};
 pub use models::{ModelKind, ModelSpec};
 pub use paths::{platform_dir, resolve_tool};
-pub use pipeline::{run_upscale_job, UpscaleJob, UpscaleJobProgress};
-pub use probe::{probe_media, MediaInfo};
-pub use upscalers::{UpscalerBinary, UpscalerKind};
+pub use pipeline::{
+    run_upscale_job, run_upscale_job_with_progress, UpscaleJob, UpscaleJobProgress,
+};
+pub use probe::{probe_media, FrameRate, MediaInfo};
+pub use scenes::{ContentClass, ModelRange, SceneModelSelection};
+pub use temporal::{StabilizeMethod, TemporalOptions};
+pub use tiles::TileCache;
+pub use upscalers::{
+    backend_for, BackendCapabilities, CpuResampler, ResampleFilter, TemplateBackend,
+    UpscaleRequest, UpscaledFormat, UpscalerBackend, UpscalerEvent, UpscalerFailure,
+    UpscalerOptions,
+};
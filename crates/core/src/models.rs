@@ -1,9 +1,23 @@
-/// Known upscaler model families supported out of the box.
-#[derive(Debug, Clone)]
+/// Upscaler model families. The first three are served by the built-in ncnn backends;
+/// `Custom` names the `UpscalerBackend::id` of a backend supplied by the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ModelKind {
     RealEsrgan,
     RealCugan,
     Waifu2x,
+    Custom(String),
+}
+
+impl ModelKind {
+    /// Identifier of the backend serving this family.
+    pub fn id(&self) -> &str {
+        match self {
+            ModelKind::RealEsrgan => "realesrgan",
+            ModelKind::RealCugan => "realcugan",
+            ModelKind::Waifu2x => "waifu2x",
+            ModelKind::Custom(id) => id,
+        }
+    }
 }
 
 /// Model specification including scale and optional denoise level.
@@ -1,11 +1,30 @@
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::Arc;
 
-use crate::encoder::EncoderOptions;
+use crate::alpha::{merge_alpha, resample_alpha, sequence_has_alpha, split_alpha, AlphaMode};
+use crate::animation::{decode_animation, encode_animation, AnimationFormat, LoopCount};
+use crate::audio::{measure_loudness, AudioOptions, AudioSelection};
+use crate::container::Container;
+use crate::dedup::{
+    find_duplicates, link_or_copy, restore_duplicates, stage_unique_frames, DedupOptions,
+};
+use crate::devices::detect_vulkan_devices;
+use crate::encoder::{check_encoder, EncoderOptions, RateControl};
 use crate::error::PixyError;
-use crate::frames::{extract_frames, FrameExtractOptions};
+use crate::frames::{extract_frames, run_ffmpeg, FrameExtractOptions};
+use crate::images::{
+    collect_input_images, is_image_output, stage_input_images, write_image_output,
+};
+use crate::interpolate::{interpolate_frames, InterpolationOptions, InterpolationPlacement};
 use crate::models::ModelSpec;
-use crate::upscalers::UpscalerBinary;
+use crate::probe::{probe_media, FrameRate, MediaInfo};
+use crate::scenes::{detect_scene_cuts, SceneModel, SceneModelSelection};
+use crate::temporal::{stabilize_frames, TemporalOptions};
+use crate::tiles::{next_tile_size, TileCache};
+use crate::upscalers::cpu::list_sequence;
+use crate::upscalers::{
+    backend_for, UpscaleRequest, UpscalerBackend, UpscalerEvent, UpscalerFailure, UpscalerOptions,
+};
 
 /// Describes a complete upscale job, including I/O, model, device, and encode options.
 #[derive(Debug, Clone)]
@@ -13,16 +32,36 @@ pub struct UpscaleJob {
     pub input: PathBuf,
     pub output: PathBuf,
     pub model: ModelSpec,
-    pub upscaler: UpscalerBinary,
+    pub upscaler: Arc<dyn UpscalerBackend>,
     pub gpu_index: usize,
-    pub tile_size: Option<u32>,
-    pub threads: Option<u32>,
+    pub upscaler_options: UpscalerOptions,
+    /// Retry remaining frames with smaller tiles when the GPU runs out of memory.
+    pub tile_fallback: bool,
     pub target_width: Option<u32>,
     pub target_height: Option<u32>,
     pub scale: Option<u32>,
     pub extract: FrameExtractOptions,
     pub encoder: EncoderOptions,
-    pub container: String,
+    /// Output container; `None` picks it from the extension of `output`, or Matroska
+    /// when writing to stdout.
+    pub container: Option<Container>,
+    /// Which audio tracks are kept and how they are encoded.
+    pub audio: AudioOptions,
+    /// How transparency in the source is upscaled.
+    pub alpha: AlphaMode,
+    /// Write an image sequence to `output` even when it does not look like one
+    /// (see `images::is_image_output`).
+    pub image_output: bool,
+    /// Per-scene or per-range models; all of them must share the scale of `model`.
+    pub scene_models: Option<SceneModelSelection>,
+    /// Upscale only unique frames and reuse the results for repeated ones.
+    pub dedup: Option<DedupOptions>,
+    /// Temporal stabilization of the upscaled frames before interpolation and encoding.
+    pub temporal: Option<TemporalOptions>,
+    /// Optional RIFE frame interpolation stage.
+    pub interpolation: Option<InterpolationOptions>,
+    /// Scratch directory for extracted and upscaled frames; defaults to `<tmp>/pixy-uppy`.
+    pub work_dir: Option<PathBuf>,
 }
 
 /// Simple progress struct for UI/CLI to display stages and percent.
@@ -35,73 +74,817 @@ pub struct UpscaleJobProgress {
 /// Runs the end-to-end pipeline: extract frames → upscale → encode & remux.
 /// Why: Central orchestration to ensure audio/subs are stream-copied and video encoded.
 pub fn run_upscale_job(job: &UpscaleJob) -> Result<(), PixyError> {
-    let temp_root = std::env::temp_dir().join("pixy-uppy");
+    run_upscale_job_with_progress(job, &mut |_| {})
+}
+
+/// Same as `run_upscale_job`, reporting stage progress through `on_progress`.
+pub fn run_upscale_job_with_progress(
+    job: &UpscaleJob,
+    on_progress: &mut dyn FnMut(UpscaleJobProgress),
+) -> Result<(), PixyError> {
+    job.encoder.validate()?;
+    if let Some(opts) = &job.interpolation {
+        opts.validate()?;
+    }
+    if job.extract.high_bit_depth && !job.upscaler.capabilities().high_bit_depth {
+        eprintln!(
+            "warning: the {} upscaler works on 8-bit frames; only the other stages keep 16 bits",
+            job.upscaler.id()
+        );
+    }
+    if let Some(selection) = &job.scene_models {
+        if selection
+            .models()
+            .iter()
+            .any(|m| m.scale != job.model.scale)
+        {
+            return Err(PixyError::InvalidArgument(
+                "per-scene models must have the same scale as the job model",
+            ));
+        }
+    }
+    let to_stdout = job.output == Path::new("-");
+    let encodes_video = encodes_video(&job.output, job.image_output);
+    if encodes_video {
+        let container = output_container(job);
+        if to_stdout && !container.is_some_and(|c| c.is_streamable()) {
+            return Err(PixyError::InvalidArgument(
+                "output to stdout needs a streamable container (mkv, webm or nut)",
+            ));
+        }
+        if container.is_none() {
+            eprintln!(
+                "warning: unknown output container; ffmpeg picks the muxer from the extension \
+                 and subtitles and attachments are only kept for inputs of the same type"
+            );
+        }
+        job.audio.validate(audio_container(container))?;
+        check_encoder(&job.encoder)?;
+    }
+    let temp_root = job
+        .work_dir
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join("pixy-uppy"));
     std::fs::create_dir_all(&temp_root)?;
     let frames_dir = temp_root.join("frames");
     let upscaled_dir = temp_root.join("upscaled");
+    let unique_dir = temp_root.join("unique");
+    let scenes_dir = temp_root.join("scenes");
+    let stabilized_dir = temp_root.join("stabilized");
+    let interpolated_dir = temp_root.join("interpolated");
+    let rgb_dir = temp_root.join("rgb");
+    let alpha_dir = temp_root.join("alpha");
+    let alpha_upscaled_dir = temp_root.join("alpha-upscaled");
+    let merged_dir = temp_root.join("merged");
+    // Leftovers from an earlier run would be mistaken for already-upscaled frames.
+    for dir in [
+        &frames_dir,
+        &unique_dir,
+        &scenes_dir,
+        &upscaled_dir,
+        &stabilized_dir,
+        &interpolated_dir,
+        &rgb_dir,
+        &alpha_dir,
+        &alpha_upscaled_dir,
+        &merged_dir,
+    ] {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+    }
 
-    let in_path = Path::new(&job.input);
-    let frames_pattern = extract_frames(in_path, &frames_dir, &job.extract)?;
-    let upscaled_pattern = upscaled_dir.join(frames_pattern.file_name().unwrap());
+    let spooled;
+    let in_path = if job.input == Path::new("-") {
+        report(on_progress, "spool", 0.0);
+        spooled = spool_stdin(&temp_root)?;
+        spooled.as_path()
+    } else {
+        job.input.as_path()
+    };
+    report(on_progress, "extract", 0.0);
+    let animation = decode_animation(in_path, &frames_dir)?;
+    let input_images = match animation {
+        Some(_) => None,
+        None => collect_input_images(in_path)?,
+    };
+    let keep_alpha = job.alpha != AlphaMode::Drop;
+    let mut source_info: Option<MediaInfo> = None;
+    // Whether frames may carry alpha. Animations are checked while decoding; images
+    // with an alpha channel are checked pixel by pixel when split.
+    let (source_rate, frames_pattern, may_have_alpha) = match (&animation, &input_images) {
+        (Some(animation), _) => (
+            animation.average_rate(),
+            Some(frames_dir.join("%08d.png")),
+            keep_alpha && animation.transparent,
+        ),
+        // Image sequences carry no timing; 24 fps matches ffmpeg's image2 default.
+        (None, Some(images)) => {
+            let pattern = stage_input_images(images, &frames_dir)?;
+            let alpha = keep_alpha && sequence_has_alpha(&pattern)?;
+            (FrameRate::new(24, 1), Some(pattern), alpha)
+        }
+        // Videos are extracted once the encoders are checked below.
+        (None, None) => {
+            let info = probe_media(in_path)?;
+            let rate = info.frame_rate().unwrap_or(FrameRate::new(24, 1));
+            let alpha = keep_alpha && info.has_alpha();
+            source_info = Some(info);
+            (rate, None, alpha)
+        }
+    };
+    let alpha_encoder = if encodes_video {
+        preflight_encoders(job, may_have_alpha)?
+    } else {
+        None
+    };
+    let frames_pattern = match (frames_pattern, &source_info) {
+        (Some(pattern), _) => pattern,
+        (None, info) => {
+            let extract = FrameExtractOptions {
+                alpha: may_have_alpha,
+                decoder: info
+                    .as_ref()
+                    .and_then(MediaInfo::alpha_decoder)
+                    .map(Into::into),
+                ..job.extract.clone()
+            };
+            extract_frames(in_path, &frames_dir, &extract)?
+        }
+    };
+    let upscaled_pattern = upscaled_dir.join(format!(
+        "%08d.{}",
+        job.upscaler_options.format().extension()
+    ));
     std::fs::create_dir_all(&upscaled_dir)?;
 
-    job.upscaler.run(
-        &frames_pattern,
-        &upscaled_pattern,
-        job.gpu_index,
-        job.tile_size,
-        job.threads,
-        &job.model,
-    )?;
-
-    let vf = build_vf(job);
-    let mut args = vec![
-        "-y".into(),
+    // Cuts are detected on the small source frames; indices are the same after upscaling.
+    let scene_cuts = match job.interpolation.as_ref().and_then(|o| o.scene_threshold) {
+        Some(threshold) => detect_scene_cuts(&list_sequence(&frames_pattern)?, threshold)?,
+        None => Vec::new(),
+    };
+    let scene_models = match &job.scene_models {
+        Some(selection) => {
+            report(on_progress, "scenes", 0.0);
+            let runs =
+                selection.assign(&list_sequence(&frames_pattern)?, source_rate, &job.model)?;
+            report(on_progress, "scenes", 100.0);
+            Some(runs)
+        }
+        None => None,
+    };
+    let interpolate = |input: &Path, on_progress: &mut dyn FnMut(UpscaleJobProgress)| {
+        let opts = job.interpolation.as_ref().expect("interpolation enabled");
+        let total = opts
+            .output_frames(list_sequence(input)?.len(), source_rate)?
+            .max(1);
+        let mut done = 0usize;
+        report(on_progress, "interpolate", 0.0);
+        interpolate_frames(
+            input,
+            &interpolated_dir,
+            source_rate,
+            job.gpu_index,
+            opts,
+            &scene_cuts,
+            &mut |event| {
+                if let UpscalerEvent::FileDone { .. } = event {
+                    done += 1;
+                    report(
+                        on_progress,
+                        "interpolate",
+                        100.0 * done as f32 / total as f32,
+                    );
+                }
+            },
+        )
+    };
+
+    let placement = job.interpolation.as_ref().map(|o| o.placement);
+    let mut rate = source_rate;
+    let mut upscale_input = frames_pattern;
+    if placement == Some(InterpolationPlacement::BeforeUpscale) {
+        (upscale_input, rate) = interpolate(&upscale_input, on_progress)?;
+    }
+    // The upscaled sequence has the same frames as `upscale_input` at this point.
+    let temporal_cuts = match job.temporal.and_then(|t| t.scene_threshold) {
+        Some(threshold) => detect_scene_cuts(&list_sequence(&upscale_input)?, threshold)?,
+        None => Vec::new(),
+    };
+    let duplicates = match &job.dedup {
+        Some(opts) => {
+            report(on_progress, "dedup", 0.0);
+            let frames = list_sequence(&upscale_input)?;
+            let map = find_duplicates(&frames, opts)?;
+            report(on_progress, "dedup", 100.0);
+            (map.unique_count() < frames.len()).then_some((frames, map))
+        }
+        None => None,
+    };
+    if let Some((frames, map)) = &duplicates {
+        upscale_input = stage_unique_frames(frames, map, &unique_dir)?;
+    }
+    let alpha_planes = if may_have_alpha {
+        split_alpha(&upscale_input, &rgb_dir, &alpha_dir)?
+    } else {
+        None
+    };
+    if let Some((rgb, _)) = &alpha_planes {
+        upscale_input = rgb.clone();
+    }
+    match &scene_models {
+        Some(runs) if runs.iter().any(|r| r.model.name != job.model.name) => upscale_scenes(
+            job,
+            runs,
+            &upscale_input,
+            rate,
+            source_rate,
+            &upscaled_pattern,
+            &scenes_dir,
+            on_progress,
+        )?,
+        _ => upscale_frames(job, &upscale_input, &upscaled_pattern, on_progress)?,
+    }
+    let mut upscaled = upscaled_pattern;
+    if let Some((_, alpha_pattern)) = &alpha_planes {
+        report(on_progress, "alpha", 0.0);
+        let alpha_upscaled = match job.alpha {
+            AlphaMode::Upscale => {
+                let pattern = alpha_upscaled_dir.join(upscaled.file_name().unwrap_or_default());
+                std::fs::create_dir_all(&alpha_upscaled_dir)?;
+                upscale_frames(job, alpha_pattern, &pattern, on_progress)?;
+                pattern
+            }
+            _ => resample_alpha(alpha_pattern, &alpha_upscaled_dir, job.model.scale)?,
+        };
+        upscaled = merge_alpha(&upscaled, &alpha_upscaled, &merged_dir)?;
+        report(on_progress, "alpha", 100.0);
+    }
+    if let Some((frames, map)) = &duplicates {
+        let dir = upscaled.parent().unwrap_or(Path::new("."));
+        let ext = upscaled.extension().unwrap_or_default().to_string_lossy();
+        restore_duplicates(frames, map, dir, &ext)?;
+    }
+    let mut encode_input = upscaled;
+    if let Some(temporal) = &job.temporal {
+        report(on_progress, "stabilize", 0.0);
+        encode_input = stabilize_frames(
+            &encode_input,
+            &stabilized_dir,
+            rate,
+            temporal,
+            &temporal_cuts,
+        )?;
+        report(on_progress, "stabilize", 100.0);
+    }
+    if placement == Some(InterpolationPlacement::AfterUpscale) {
+        (encode_input, rate) = interpolate(&encode_input, on_progress)?;
+    }
+
+    let frames = list_sequence(&encode_input)?;
+    let animation_output = AnimationFormat::for_output(&job.output, frames.len());
+    if let (false, Some(format)) = (job.image_output, animation_output) {
+        report(on_progress, "write", 0.0);
+        // Source delays only apply while frames still map one to one.
+        let (delays, loop_count) = match &animation {
+            Some(a) if a.delays_ms.len() == frames.len() => (a.delays_ms.clone(), a.loop_count),
+            a => (
+                vec![1000.0 / rate.as_f64(); frames.len()],
+                a.as_ref().map_or(LoopCount::Infinite, |a| a.loop_count),
+            ),
+        };
+        encode_animation(
+            &frames,
+            &delays,
+            loop_count,
+            format,
+            &job.output,
+            &temp_root,
+        )?;
+        report(on_progress, "write", 100.0);
+        return Ok(());
+    }
+
+    if job.image_output || is_image_output(&job.output) {
+        report(on_progress, "write", 0.0);
+        // Interpolation changes the frame count, so input names no longer line up.
+        let names = input_images
+            .as_deref()
+            .filter(|names| names.len() == frames.len());
+        write_image_output(&frames, names, &job.output)?;
+        report(on_progress, "write", 100.0);
+        return Ok(());
+    }
+
+    let container = output_container(job);
+    let audio_tracks = source_info
+        .as_ref()
+        .map(|info| job.audio.plan(audio_container(container), info))
+        .unwrap_or_default();
+    if audio_tracks.is_empty()
+        && job.audio.selection != AudioSelection::None
+        && source_info
+            .as_ref()
+            .is_some_and(|info| info.audio_streams().next().is_some())
+    {
+        eprintln!("warning: no audio track matches the selection; the output has no audio");
+    }
+    if !job.audio.transcodes() {
+        for track in &audio_tracks {
+            if let Some(codec) = track.codec {
+                eprintln!(
+                    "warning: {} cannot store {} audio; track {} is transcoded to {}",
+                    audio_container(container).muxer(),
+                    track.source_codec,
+                    track.index,
+                    codec.ffmpeg_name()
+                );
+            }
+        }
+    }
+    let loudness = match &job.audio.loudness {
+        Some(target) => {
+            report(on_progress, "loudness", 0.0);
+            let measured = audio_tracks
+                .iter()
+                .map(|track| measure_loudness(in_path, track.index, target))
+                .collect::<Result<Vec<_>, _>>()?;
+            report(on_progress, "loudness", 100.0);
+            measured
+        }
+        None => Vec::new(),
+    };
+
+    report(on_progress, "encode", 0.0);
+    let alpha_encoder = match alpha_planes {
+        Some(_) if alpha_encoder.is_none() => {
+            eprintln!("warning: the output container cannot store transparency; alpha is dropped");
+            None
+        }
+        Some(_) => alpha_encoder,
+        None => None,
+    };
+    if let Some(encoder) = alpha_encoder
+        .as_ref()
+        .filter(|e| e.rate_control != job.encoder.rate_control)
+    {
+        let name = encoder.encoder.ffmpeg_name();
+        match encoder.rate_control {
+            Some(RateControl::ConstantQuality(q)) => {
+                eprintln!("warning: quality clamped to {q} for {name}, which keeps transparency")
+            }
+            _ => eprintln!(
+                "warning: {name} has no bitrate or lossless mode; transparency is encoded at its default quality"
+            ),
+        }
+    }
+    let encoder = alpha_encoder.as_ref().unwrap_or(&job.encoder);
+    if let Some(container) = container {
+        container.check_encoder(encoder.encoder)?;
+    }
+    let encoder = match encoder.rate_control {
+        Some(RateControl::TargetSize { .. }) => {
+            let duration = source_info
+                .as_ref()
+                .and_then(MediaInfo::duration)
+                .unwrap_or(frames.len() as f64 / rate.as_f64());
+            // The audio shares the size budget with the video.
+            let audio_bps = match audio_tracks.iter().map(|t| t.bitrate).sum::<Option<u64>>() {
+                Some(bps) => bps,
+                None => {
+                    eprintln!(
+                        "warning: audio bitrate unknown; the output may exceed the target size"
+                    );
+                    0
+                }
+            };
+            encoder.resolve_target_size(duration, audio_bps)?
+        }
+        _ => encoder.clone(),
+    };
+    let vf = build_vf(job, &encoder);
+    let mut inputs: Vec<String> = vec!["-y".into()];
+    inputs.extend(encoder.hw_device_args());
+    inputs.extend([
         "-framerate".into(),
-        "24".into(),
+        rate.to_string(),
         "-i".into(),
-        upscaled_pattern.to_string_lossy().to_string(),
-        "-i".into(),
-        job.input.to_string_lossy().to_string(),
-        "-map".into(),
-        "0:v:0".into(),
-        "-map".into(),
-        "1:a?".into(),
-        "-map".into(),
-        "1:s?".into(),
-        "-map".into(),
-        "1:t?".into(),
-        "-c:a".into(),
-        "copy".into(),
-        "-c:s".into(),
-        "copy".into(),
-        "-c:t".into(),
-        "copy".into(),
-    ];
+        encode_input.to_string_lossy().to_string(),
+    ]);
+    let stats = temp_root.join("passlog");
+    if encoder.is_two_pass() {
+        // The first pass only gathers statistics, so it skips the streams copied over.
+        let mut args = inputs.clone();
+        if let Some(vf) = &vf {
+            args.extend(["-vf".into(), vf.clone()]);
+        }
+        args.extend(encoder.pass_ffmpeg_args(1, &stats));
+        args.extend(["-f".into(), "null".into(), "-".into()]);
+        run_ffmpeg(&args)?;
+        report(on_progress, "encode", 50.0);
+    }
+    let mut args = inputs;
+    // Image and animation inputs have no audio, subtitles or attachments to carry over.
+    if input_images.is_none() && animation.is_none() {
+        args.extend([
+            "-i".into(),
+            in_path.to_string_lossy().to_string(),
+            "-map".into(),
+            "0:v:0".into(),
+        ]);
+        for (out, track) in audio_tracks.iter().enumerate() {
+            args.extend(job.audio.track_args(
+                track,
+                out,
+                loudness.get(out).and_then(Option::as_ref),
+            ));
+        }
+        if let Some(info) = &source_info {
+            args.extend(match container {
+                Some(container) => source_stream_args(container, info),
+                None => unknown_container_stream_args(job, info),
+            });
+        }
+    }
 
     if let Some(vf) = vf {
         args.push("-vf".into());
         args.push(vf);
     }
-    args.extend(job.encoder.to_ffmpeg_args());
-    args.push(job.output.to_string_lossy().to_string());
+    if encoder.is_two_pass() {
+        args.extend(encoder.pass_ffmpeg_args(2, &stats));
+    } else {
+        args.extend(encoder.to_ffmpeg_args());
+    }
+    if let Some(container) = container {
+        args.extend(["-f".into(), container.muxer().into()]);
+        if !to_stdout {
+            args.extend(container.muxer_args());
+        }
+    }
+    if to_stdout {
+        args.push("-".into());
+    } else {
+        args.push(job.output.to_string_lossy().to_string());
+    }
+    run_ffmpeg(&args)?;
+    report(on_progress, "encode", 100.0);
+
+    Ok(())
+}
+
+/// Whether a job writing to `output` ends in a video encode, rather than images
+/// (`image_output` forces those) or an animated GIF/APNG/WebP.
+pub fn encodes_video(output: &Path, image_output: bool) -> bool {
+    !image_output && !is_image_output(output) && AnimationFormat::for_output(output, 2).is_none()
+}
 
-    let ffmpeg = crate::paths::resolve_tool("ffmpeg")?;
-    let status = Command::new(ffmpeg).args(args.clone()).status()?;
-    if !status.success() {
-        return Err(PixyError::ProcessFailed {
-            cmd: format!("ffmpeg {:?}", args),
-            code: status.code(),
-            stderr: String::new(),
+/// Copies stdin into `dir` so it can be probed, extracted and remuxed from like a file.
+/// Why: Those steps each read the input, which a pipe only allows once.
+fn spool_stdin(dir: &Path) -> Result<PathBuf, PixyError> {
+    let path = dir.join("stdin");
+    let mut file = std::fs::File::create(&path)?;
+    std::io::copy(&mut std::io::stdin().lock(), &mut file)?;
+    Ok(path)
+}
+
+/// Container `job` is written in: the configured one, else the one the output
+/// extension names, else Matroska for stdout. `None` for other extensions, where
+/// ffmpeg picks the muxer itself.
+fn output_container(job: &UpscaleJob) -> Option<Container> {
+    match (job.container, job.output == Path::new("-")) {
+        (Some(container), _) => Some(container),
+        (None, true) => Some(Container::Mkv),
+        (None, false) => Container::for_output(&job.output),
+    }
+}
+
+/// Checks the encoder against the output container before anything slow runs, and
+/// resolves the alpha-capable encoder transparent frames switch to, which is also
+/// validated and checked against ffmpeg. Returns that encoder, or `None` when the
+/// frames are opaque or the container cannot carry alpha video.
+/// Why: Otherwise a missing alpha encoder only surfaces after the upscale.
+fn preflight_encoders(
+    job: &UpscaleJob,
+    may_have_alpha: bool,
+) -> Result<Option<EncoderOptions>, PixyError> {
+    let container = output_container(job);
+    let alpha_encoder = if may_have_alpha {
+        job.encoder.with_alpha(container)
+    } else {
+        None
+    };
+    // Frames that turn out opaque fall back to the job's encoder, which is checked
+    // against the container again before encoding.
+    let encoder = alpha_encoder.as_ref().unwrap_or(&job.encoder);
+    if let Some(container) = container {
+        container.check_encoder(encoder.encoder)?;
+    }
+    if let Some(alpha_encoder) = &alpha_encoder {
+        alpha_encoder.validate()?;
+        // The job's own encoder was checked on entry.
+        if alpha_encoder.encoder != job.encoder.encoder {
+            check_encoder(alpha_encoder)?;
+        }
+    }
+    Ok(alpha_encoder)
+}
+
+/// Container the audio is planned for. Matroska takes every codec, so unknown
+/// containers keep copying audio as is.
+fn audio_container(container: Option<Container>) -> Container {
+    container.unwrap_or(Container::Mkv)
+}
+
+/// Subtitles and attachments for a container ffmpeg picks from the extension: copied
+/// when the output has the input's extension, which already holds them, and dropped
+/// with a warning otherwise.
+fn unknown_container_stream_args(job: &UpscaleJob, info: &MediaInfo) -> Vec<String> {
+    let extension = |path: &Path| {
+        path.extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+    };
+    let same_type =
+        extension(&job.input).is_some() && extension(&job.input) == extension(&job.output);
+    if same_type {
+        return [
+            "-map", "1:s?", "-map", "1:t?", "-c:s", "copy", "-c:t", "copy",
+        ]
+        .map(String::from)
+        .into();
+    }
+    if info
+        .streams
+        .iter()
+        .any(|s| s["codec_type"] == "subtitle" || s["codec_type"] == "attachment")
+    {
+        eprintln!(
+            "warning: subtitles and attachments are dropped; the output container is unknown"
+        );
+    }
+    Vec::new()
+}
+
+/// Maps the subtitles and attachments of the source (input 1) into `container`,
+/// converting text subtitles it cannot copy and dropping the rest with a warning.
+/// Why: MP4 only takes `mov_text` and WebM only WebVTT, so copying PGS or ASS fails
+/// the whole mux; attachments only exist in Matroska.
+fn source_stream_args(container: Container, info: &MediaInfo) -> Vec<String> {
+    let mut args = Vec::new();
+    let subtitles = info
+        .streams
+        .iter()
+        .filter(|s| s["codec_type"] == "subtitle");
+    let mut kept = 0;
+    for (index, stream) in subtitles.enumerate() {
+        let codec = stream["codec_name"].as_str().unwrap_or("unknown");
+        match container.subtitle_codec(codec) {
+            Some(target) => {
+                args.extend([
+                    "-map".into(),
+                    format!("1:s:{index}"),
+                    format!("-c:s:{kept}"),
+                    target.into(),
+                ]);
+                kept += 1;
+            }
+            None => eprintln!(
+                "warning: {} cannot store {codec} subtitles; subtitle stream {index} is dropped",
+                container.muxer()
+            ),
+        }
+    }
+    if container.supports_attachments() {
+        args.extend(["-map", "1:t?", "-c:t", "copy"].map(String::from));
+    } else if info.streams.iter().any(|s| s["codec_type"] == "attachment") {
+        eprintln!(
+            "warning: {} cannot store attachments; fonts and other attachments are dropped",
+            container.muxer()
+        );
+    }
+    args
+}
+
+/// Upscales the extracted frame sequence into `output_pattern`. When the GPU runs out of
+/// memory, the frames still missing are retried with progressively smaller tiles and the
+/// tile size that worked is remembered for this device and model.
+/// Why: Tile sizes that fit VRAM vary per card; a failure hours in should not kill the job.
+fn upscale_frames(
+    job: &UpscaleJob,
+    frames_pattern: &Path,
+    output_pattern: &Path,
+    on_progress: &mut dyn FnMut(UpscaleJobProgress),
+) -> Result<(), PixyError> {
+    let frames_dir = frames_pattern.parent().unwrap_or(Path::new("."));
+    let upscaled_dir = output_pattern.parent().unwrap_or(Path::new("."));
+    let total_frames = std::fs::read_dir(frames_dir)?.count().max(1);
+
+    // Backends without a tile option (e.g. templates lacking `{tile}`) cannot fall back.
+    let device = (job.tile_fallback && job.upscaler.capabilities().tile_size)
+        .then(|| device_label(job.gpu_index));
+    let mut cache = TileCache::load();
+    let mut options = job.upscaler_options.clone();
+    if let (None, Some(device)) = (options.tile_size, &device) {
+        options.tile_size = cache.get(device, &job.model.name);
+    }
+
+    let mut input_pattern = frames_pattern.to_path_buf();
+    let mut fell_back = false;
+    loop {
+        report(on_progress, "upscale", 0.0);
+        // Scenes share `upscaled_dir`, so only this run's frames count.
+        let mut done = upscaled_count(frames_dir, upscaled_dir, &job.upscaler_options)?;
+        let request = UpscaleRequest {
+            input_pattern: &input_pattern,
+            output_pattern,
+            gpu: job.gpu_index,
+            options: &options,
+            model: &job.model,
+        };
+        let result = job.upscaler.run(&request, &mut |event| match event {
+            UpscalerEvent::Progress { percent } => {
+                let current = (done as f32 + percent / 100.0).min(total_frames as f32);
+                report(
+                    on_progress,
+                    "upscale",
+                    100.0 * current / total_frames as f32,
+                );
+            }
+            UpscalerEvent::FileDone { .. } => {
+                done = (done + 1).min(total_frames);
+                report(
+                    on_progress,
+                    "upscale",
+                    100.0 * done as f32 / total_frames as f32,
+                );
+            }
+            UpscalerEvent::Log(line) => eprintln!("{line}"),
         });
+
+        let err = match result {
+            Ok(()) => break,
+            Err(err) => err,
+        };
+        let Some(device) = &device else {
+            return Err(err);
+        };
+        if err.upscaler_failure() != Some(UpscalerFailure::OutOfMemory) {
+            return Err(err);
+        }
+        let Some(tile) = next_tile_size(options.tile_size.unwrap_or(0)) else {
+            return Err(err);
+        };
+        eprintln!("warning: GPU {device} ran out of memory; retrying remaining frames with tile size {tile}");
+        options.tile_size = Some(tile);
+        let pending_dir = stage_pending_frames(frames_dir, upscaled_dir, &job.upscaler_options)?;
+        input_pattern = pending_dir.join(frames_pattern.file_name().unwrap_or_default());
+        fell_back = true;
     }
 
+    if let (true, Some(device), Some(tile)) = (fell_back, &device, options.tile_size) {
+        if let Err(err) = cache.remember(device, &job.model.name, tile) {
+            eprintln!("warning: could not save tile size cache ({err})");
+        }
+    }
     Ok(())
 }
 
-/// Builds a video filter string for optional post-scaling to the requested target.
+/// Upscales the frames of each scene run with that run's model into `output_pattern`.
+/// `input_rate` is the rate of the input sequence, which differs from `source_rate`
+/// when interpolation ran first.
+#[allow(clippy::too_many_arguments)]
+fn upscale_scenes(
+    job: &UpscaleJob,
+    runs: &[SceneModel],
+    input_pattern: &Path,
+    input_rate: FrameRate,
+    source_rate: FrameRate,
+    output_pattern: &Path,
+    scenes_dir: &Path,
+    on_progress: &mut dyn FnMut(UpscaleJobProgress),
+) -> Result<(), PixyError> {
+    let mut groups: Vec<Vec<PathBuf>> = vec![Vec::new(); runs.len()];
+    for frame in list_sequence(input_pattern)? {
+        // Frames are numbered from 1; dedup may leave gaps, so use the name, not the position.
+        let index = frame
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<usize>().ok())
+            .map_or(0, |n| n.saturating_sub(1));
+        let source = (index as f64 * source_rate.as_f64() / input_rate.as_f64() + 1e-6) as usize;
+        let run = runs
+            .iter()
+            .position(|r| r.frames.contains(&source))
+            .unwrap_or(runs.len() - 1);
+        groups[run].push(frame);
+    }
+
+    for (n, (run, group)) in runs.iter().zip(groups).enumerate() {
+        if group.is_empty() {
+            continue;
+        }
+        let dir = scenes_dir.join(n.to_string());
+        std::fs::create_dir_all(&dir)?;
+        for frame in &group {
+            link_or_copy(frame, &dir.join(frame.file_name().unwrap_or_default()))?;
+        }
+        // A backend without GPU selection (the CPU fallback) stands in for every model;
+        // `backend_for` would bring back the ncnn binary it replaced.
+        let (upscaler, model) = if !job.upscaler.capabilities().gpu_selection {
+            let model = ModelSpec {
+                denoise_level: None,
+                ..run.model.clone()
+            };
+            (job.upscaler.clone(), model)
+        } else if run.model.kind == job.model.kind {
+            (job.upscaler.clone(), run.model.clone())
+        } else {
+            let upscaler = backend_for(&run.model.kind).ok_or(PixyError::InvalidArgument(
+                "no upscaler backend for per-scene model",
+            ))?;
+            (upscaler, run.model.clone())
+        };
+        let scene_job = UpscaleJob {
+            model,
+            upscaler,
+            ..job.clone()
+        };
+        let pattern = dir.join(input_pattern.file_name().unwrap_or_default());
+        upscale_frames(&scene_job, &pattern, output_pattern, on_progress)?;
+    }
+    Ok(())
+}
+
+/// Number of frames in `frames_dir` that already have an upscaled counterpart.
+fn upscaled_count(
+    frames_dir: &Path,
+    upscaled_dir: &Path,
+    options: &UpscalerOptions,
+) -> Result<usize, PixyError> {
+    let mut count = 0;
+    for entry in std::fs::read_dir(frames_dir)? {
+        let frame = entry?.path();
+        let Some(stem) = frame.file_stem() else {
+            continue;
+        };
+        let upscaled = upscaled_dir
+            .join(stem)
+            .with_extension(options.format().extension());
+        if upscaled.exists() {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Links (or copies) the frames that have no upscaled counterpart yet into a fresh
+/// `pending` directory next to `frames_dir`, returning that directory.
+fn stage_pending_frames(
+    frames_dir: &Path,
+    upscaled_dir: &Path,
+    options: &UpscalerOptions,
+) -> Result<PathBuf, PixyError> {
+    let pending_dir = frames_dir.with_file_name("pending");
+    if pending_dir.exists() {
+        std::fs::remove_dir_all(&pending_dir)?;
+    }
+    std::fs::create_dir_all(&pending_dir)?;
+    for entry in std::fs::read_dir(frames_dir)? {
+        let frame = entry?.path();
+        let Some(stem) = frame.file_stem() else {
+            continue;
+        };
+        let upscaled = upscaled_dir
+            .join(stem)
+            .with_extension(options.format().extension());
+        if upscaled.exists() {
+            continue;
+        }
+        link_or_copy(&frame, &pending_dir.join(frame.file_name().unwrap_or(stem)))?;
+    }
+    Ok(pending_dir)
+}
+
+/// Stable name for the selected GPU, used as the tile cache key.
+fn device_label(gpu_index: usize) -> String {
+    detect_vulkan_devices()
+        .ok()
+        .and_then(|devices| devices.into_iter().find(|d| d.index == gpu_index))
+        .map(|d| d.name)
+        .unwrap_or_else(|| format!("gpu{gpu_index}"))
+}
+
+fn report(on_progress: &mut dyn FnMut(UpscaleJobProgress), stage: &str, percent: f32) {
+    on_progress(UpscaleJobProgress {
+        stage: stage.into(),
+        percent,
+    });
+}
+
+/// Builds a video filter string for optional post-scaling to the requested target,
+/// followed by the hardware upload VAAPI and QSV encoders need.
 /// Why: Models may output fixed scales; use high-quality scaler to hit exact resolution.
-fn build_vf(job: &UpscaleJob) -> Option<String> {
+fn build_vf(job: &UpscaleJob, encoder: &EncoderOptions) -> Option<String> {
     let mut vf_parts: Vec<String> = Vec::new();
     match (job.target_width, job.target_height, job.scale) {
         (Some(w), Some(h), _) => vf_parts.push(format!("zscale=w={}:h={}:filter=spline36", w, h)),
@@ -110,6 +893,8 @@ fn build_vf(job: &UpscaleJob) -> Option<String> {
         (None, None, Some(_s)) => { /* rely on model */ }
         _ => {}
     }
+    // Scaling runs on software frames, so the upload has to come last.
+    vf_parts.extend(encoder.hw_upload_filter());
     if vf_parts.is_empty() {
         None
     } else {
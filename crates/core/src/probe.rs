@@ -12,6 +12,123 @@ pub struct MediaInfo {
     pub streams: Vec<serde_json::Value>,
 }
 
+/// Rational frame rate as reported by ffprobe (e.g. `24000/1001`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl FrameRate {
+    pub fn new(num: u32, den: u32) -> Self {
+        Self { num, den }
+    }
+
+    /// Parses `num/den` or a plain number such as `60` or `29.97`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some((n, d)) = s.split_once('/') {
+            let (num, den) = (n.trim().parse().ok()?, d.trim().parse().ok()?);
+            return (num > 0 && den > 0).then_some(Self { num, den });
+        }
+        let fps: f64 = s.parse().ok()?;
+        if fps.is_nan() || fps <= 0.0 {
+            return None;
+        }
+        // Keep three decimals, enough for NTSC rates written as 29.97 or 59.94.
+        let num = (fps * 1000.0).round() as u32;
+        Some(Self { num, den: 1000 })
+    }
+
+    pub fn as_f64(self) -> f64 {
+        f64::from(self.num) / f64::from(self.den)
+    }
+
+    /// Returns the rate multiplied by an integer factor.
+    pub fn times(self, factor: u32) -> Result<Self, PixyError> {
+        let num = self
+            .num
+            .checked_mul(factor)
+            .ok_or(PixyError::InvalidArgument(
+                "frame rate multiplier is too large",
+            ))?;
+        Ok(Self { num, den: self.den })
+    }
+}
+
+impl std::fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+impl MediaInfo {
+    /// First video stream, if any.
+    pub fn video_stream(&self) -> Option<&serde_json::Value> {
+        self.streams.iter().find(|s| s["codec_type"] == "video")
+    }
+
+    /// Frame rate of the first video stream, preferring the average rate (correct for
+    /// most VFR sources) over the container's nominal `r_frame_rate`.
+    pub fn frame_rate(&self) -> Option<FrameRate> {
+        let video = self.video_stream()?;
+        ["avg_frame_rate", "r_frame_rate"]
+            .iter()
+            .filter_map(|key| video[*key].as_str())
+            .find_map(FrameRate::parse)
+    }
+
+    /// Whether the first video stream carries transparency: an alpha pixel format, or
+    /// VP8/VP9 with the WebM `alpha_mode` tag (ffprobe reports those as `yuv420p`).
+    pub fn has_alpha(&self) -> bool {
+        let Some(video) = self.video_stream() else {
+            return false;
+        };
+        let pix_fmt = video["pix_fmt"].as_str().unwrap_or_default();
+        let alpha_fmt = pix_fmt.starts_with("yuva")
+            || pix_fmt.starts_with("gbrap")
+            || pix_fmt.starts_with("ya")
+            || ["rgba", "bgra", "argb", "abgr", "rgba64", "bgra64"]
+                .iter()
+                .any(|f| pix_fmt.starts_with(f));
+        alpha_fmt || self.alpha_decoder().is_some()
+    }
+
+    /// Decoder needed to read alpha: libvpx for VP8/VP9 with side-channel alpha.
+    pub fn alpha_decoder(&self) -> Option<&'static str> {
+        let video = self.video_stream()?;
+        let tags = &video["tags"];
+        let alpha_mode = tags["alpha_mode"].as_str().or(tags["ALPHA_MODE"].as_str());
+        if alpha_mode != Some("1") {
+            return None;
+        }
+        match video["codec_name"].as_str()? {
+            "vp9" => Some("libvpx-vp9"),
+            "vp8" => Some("libvpx"),
+            _ => None,
+        }
+    }
+
+    /// Container duration in seconds.
+    pub fn duration(&self) -> Option<f64> {
+        self.format["duration"].as_str()?.parse().ok()
+    }
+
+    /// Audio streams in order, so the n-th one is ffmpeg's `a:n`.
+    pub fn audio_streams(&self) -> impl Iterator<Item = &serde_json::Value> {
+        self.streams.iter().filter(|s| s["codec_type"] == "audio")
+    }
+}
+
+/// Bitrate of a probed stream in bits per second; Matroska only reports it through the
+/// `BPS` tag.
+pub fn stream_bitrate(stream: &serde_json::Value) -> Option<u64> {
+    let tags = &stream["tags"];
+    [&stream["bit_rate"], &tags["BPS"], &tags["BPS-eng"]]
+        .iter()
+        .find_map(|v| v.as_str()?.parse().ok())
+}
+
 /// Probes a media file using ffprobe and returns streams and format JSON.
 /// Why: We need fps, resolution, and stream mapping to preserve audio/subs.
 pub fn probe_media(input: &Path) -> Result<MediaInfo, PixyError> {
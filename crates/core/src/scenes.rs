@@ -0,0 +1,276 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use image::imageops::FilterType;
+use serde::Deserialize;
+
+use crate::error::PixyError;
+use crate::models::{curated_models, ModelSpec};
+use crate::probe::FrameRate;
+use crate::upscalers::cpu::image_error;
+
+/// Share of flat pixels above which a frame is classified as animation.
+const FLAT_ANIMATION_SHARE: f32 = 0.45;
+
+/// How the pipeline picks a model for each part of the video.
+#[derive(Debug, Clone)]
+pub enum SceneModelSelection {
+    /// Explicit time ranges; frames outside every range use the job model.
+    Ranges(Vec<ModelRange>),
+    /// Detect scene cuts, classify each scene and use the matching model.
+    Auto {
+        /// Scene change threshold in `0.0..=1.0`.
+        threshold: f32,
+        animation: ModelSpec,
+        live_action: ModelSpec,
+    },
+}
+
+/// A model for the half-open time range `start..end` (seconds; open-ended when `end` is `None`).
+#[derive(Debug, Clone)]
+pub struct ModelRange {
+    pub start: f64,
+    pub end: Option<f64>,
+    pub model: ModelSpec,
+}
+
+/// Coarse content class used for automatic model selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentClass {
+    Animation,
+    LiveAction,
+}
+
+/// A run of consecutive source frames upscaled with one model.
+#[derive(Debug, Clone)]
+pub struct SceneModel {
+    pub frames: Range<usize>,
+    pub model: ModelSpec,
+}
+
+#[derive(Deserialize)]
+struct RangesFile {
+    #[serde(default)]
+    range: Vec<RangeEntry>,
+}
+
+#[derive(Deserialize)]
+struct RangeEntry {
+    start: TimeValue,
+    #[serde(default)]
+    end: Option<TimeValue>,
+    model: String,
+}
+
+/// Seconds as a number, or a `[[hh:]mm:]ss[.fff]` timestamp.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TimeValue {
+    Seconds(f64),
+    Timestamp(String),
+}
+
+impl TimeValue {
+    fn seconds(&self) -> Option<f64> {
+        match self {
+            TimeValue::Seconds(s) => Some(*s),
+            TimeValue::Timestamp(text) => parse_timestamp(text),
+        }
+    }
+}
+
+impl ModelRange {
+    /// Parses a ranges file; `model` names refer to `curated_models()`.
+    ///
+    /// ```toml
+    /// [[range]]
+    /// start = "0:00"
+    /// end = "1:30.5"
+    /// model = "realesrgan-x4plus"
+    /// ```
+    pub fn from_toml_str(source: &str) -> Result<Vec<Self>, PixyError> {
+        let file: RangesFile = toml::from_str(source)?;
+        let models = curated_models();
+        file.range
+            .into_iter()
+            .map(|entry| {
+                let start = entry
+                    .start
+                    .seconds()
+                    .ok_or(PixyError::InvalidArgument("invalid range start time"))?;
+                let end = match &entry.end {
+                    Some(end) => Some(
+                        end.seconds()
+                            .ok_or(PixyError::InvalidArgument("invalid range end time"))?,
+                    ),
+                    None => None,
+                };
+                if start < 0.0 || end.is_some_and(|end| end <= start) {
+                    return Err(PixyError::InvalidArgument(
+                        "range end must come after its start",
+                    ));
+                }
+                let model = models
+                    .iter()
+                    .find(|m| m.name == entry.model)
+                    .cloned()
+                    .ok_or(PixyError::InvalidArgument("unknown model in ranges file"))?;
+                Ok(Self { start, end, model })
+            })
+            .collect()
+    }
+
+    /// Loads a ranges file from disk.
+    pub fn from_file(path: &Path) -> Result<Vec<Self>, PixyError> {
+        Self::from_toml_str(&std::fs::read_to_string(path)?)
+    }
+
+    fn contains(&self, seconds: f64) -> bool {
+        seconds >= self.start && self.end.is_none_or(|end| seconds < end)
+    }
+}
+
+impl SceneModelSelection {
+    /// Splits the source frames into runs that share a model.
+    /// Why: Consecutive frames with the same model are upscaled in one backend call.
+    pub fn assign(
+        &self,
+        frames: &[PathBuf],
+        rate: FrameRate,
+        default: &ModelSpec,
+    ) -> Result<Vec<SceneModel>, PixyError> {
+        let mut runs: Vec<SceneModel> = Vec::new();
+        let mut push = |frames: Range<usize>, model: &ModelSpec| match runs.last_mut() {
+            Some(last) if last.model.name == model.name && last.frames.end == frames.start => {
+                last.frames.end = frames.end;
+            }
+            _ => runs.push(SceneModel {
+                frames,
+                model: model.clone(),
+            }),
+        };
+        match self {
+            SceneModelSelection::Ranges(ranges) => {
+                for i in 0..frames.len() {
+                    let seconds = i as f64 / rate.as_f64();
+                    let model = ranges
+                        .iter()
+                        .find(|r| r.contains(seconds))
+                        .map_or(default, |r| &r.model);
+                    push(i..i + 1, model);
+                }
+            }
+            SceneModelSelection::Auto {
+                threshold,
+                animation,
+                live_action,
+            } => {
+                let cuts = detect_scene_cuts(frames, *threshold)?;
+                for scene in split_scenes(&cuts, frames.len()) {
+                    // The middle frame avoids fades at either end of the scene.
+                    let middle = &frames[(scene.start + scene.end) / 2];
+                    let model = match classify_frame(middle)? {
+                        ContentClass::Animation => animation,
+                        ContentClass::LiveAction => live_action,
+                    };
+                    push(scene, model);
+                }
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Every model this selection may use.
+    pub fn models(&self) -> Vec<&ModelSpec> {
+        match self {
+            SceneModelSelection::Ranges(ranges) => ranges.iter().map(|r| &r.model).collect(),
+            SceneModelSelection::Auto {
+                animation,
+                live_action,
+                ..
+            } => vec![animation, live_action],
+        }
+    }
+}
+
+/// Detects scene cuts in an image sequence by comparing downscaled luma of consecutive
+/// frames, returning the index of the first frame of every new scene.
+/// Why: A cheap mean-absolute-difference score (as in ffmpeg's `scene` metric) is
+/// enough to tell hard cuts from motion.
+pub fn detect_scene_cuts(frames: &[PathBuf], threshold: f32) -> Result<Vec<usize>, PixyError> {
+    let mut cuts = Vec::new();
+    let mut previous: Option<Vec<u8>> = None;
+    for (i, frame) in frames.iter().enumerate() {
+        let luma = image::open(frame)
+            .map_err(image_error)?
+            .resize_exact(64, 36, FilterType::Triangle)
+            .to_luma8()
+            .into_raw();
+        if let Some(prev) = &previous {
+            let diff: u64 = prev
+                .iter()
+                .zip(&luma)
+                .map(|(a, b)| u64::from(a.abs_diff(*b)))
+                .sum();
+            let score = diff as f32 / (luma.len() as f32 * 255.0);
+            if score > threshold {
+                cuts.push(i);
+            }
+        }
+        previous = Some(luma);
+    }
+    Ok(cuts)
+}
+
+/// Frame ranges of the scenes delimited by `cuts`.
+pub fn split_scenes(cuts: &[usize], frame_count: usize) -> Vec<Range<usize>> {
+    let mut scenes = Vec::with_capacity(cuts.len() + 1);
+    let mut start = 0;
+    for &cut in cuts.iter().chain(std::iter::once(&frame_count)) {
+        if cut > start && cut <= frame_count {
+            scenes.push(start..cut);
+            start = cut;
+        }
+    }
+    scenes
+}
+
+/// Classifies a frame as animation or live action from the share of flat pixels.
+/// Why: Cel animation is dominated by flat fills, while camera footage carries grain
+/// and texture almost everywhere; frames that are mostly black (credits) count as flat.
+pub fn classify_frame(frame: &Path) -> Result<ContentClass, PixyError> {
+    let (w, h) = (160usize, 90usize);
+    let luma = image::open(frame)
+        .map_err(image_error)?
+        .resize_exact(w as u32, h as u32, FilterType::Triangle)
+        .to_luma8()
+        .into_raw();
+    let mut flat = 0usize;
+    for y in 0..h - 1 {
+        for x in 0..w - 1 {
+            let p = luma[y * w + x];
+            if p.abs_diff(luma[y * w + x + 1]) <= 1 && p.abs_diff(luma[(y + 1) * w + x]) <= 1 {
+                flat += 1;
+            }
+        }
+    }
+    let share = flat as f32 / ((w - 1) * (h - 1)) as f32;
+    Ok(if share >= FLAT_ANIMATION_SHARE {
+        ContentClass::Animation
+    } else {
+        ContentClass::LiveAction
+    })
+}
+
+fn parse_timestamp(text: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    for part in text.trim().split(':') {
+        let value: f64 = part.trim().parse().ok()?;
+        // `-0` and `nan` parse as floats too.
+        if value.is_sign_negative() || !value.is_finite() {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
+}
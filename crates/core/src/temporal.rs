@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::PixyError;
+use crate::frames::run_ffmpeg;
+use crate::probe::FrameRate;
+use crate::scenes::split_scenes;
+use crate::upscalers::cpu::list_sequence;
+
+/// Temporal filter used to calm frame-to-frame shimmer in the upscaled sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StabilizeMethod {
+    /// Adaptive temporal averaging (`atadenoise`): averages only pixels that barely
+    /// change, so moving edges are left alone.
+    Atadenoise,
+    /// Temporal-only `hqdn3d`: motion-adaptive recursive smoothing.
+    Hqdn3d,
+    /// Weighted blend of each frame with its neighbours (`tmix`); ghosts on fast motion.
+    Tmix,
+    /// Brightness flicker removal (`deflicker`); does not touch texture shimmer.
+    Deflicker,
+}
+
+/// Options for the temporal stabilization stage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemporalOptions {
+    pub method: StabilizeMethod,
+    /// `0.0..=1.0`; higher values smooth more at the cost of fine detail.
+    pub strength: f32,
+    /// Scene change threshold in `0.0..=1.0`; each scene is filtered on its own so no
+    /// frame is blended with one across a cut. `None` filters the clip as a whole.
+    pub scene_threshold: Option<f32>,
+}
+
+impl Default for TemporalOptions {
+    fn default() -> Self {
+        Self {
+            method: StabilizeMethod::Atadenoise,
+            strength: 0.5,
+            scene_threshold: Some(0.3),
+        }
+    }
+}
+
+impl TemporalOptions {
+    /// ffmpeg filter implementing the method at the configured strength.
+    pub fn filter(&self) -> Result<String, PixyError> {
+        if !(0.0..=1.0).contains(&self.strength) {
+            return Err(PixyError::InvalidArgument(
+                "temporal strength must be between 0 and 1",
+            ));
+        }
+        if self
+            .scene_threshold
+            .is_some_and(|t| !(0.0..=1.0).contains(&t))
+        {
+            return Err(PixyError::InvalidArgument(
+                "scene threshold must be between 0.0 and 1.0",
+            ));
+        }
+        let s = self.strength;
+        Ok(match self.method {
+            StabilizeMethod::Atadenoise => {
+                let a = 0.01 + 0.05 * s;
+                let b = 0.02 + 0.1 * s;
+                // Window length must be odd.
+                let frames = 5 + 2 * (4.0 * s).round() as u32;
+                format!("atadenoise=0a={a:.3}:1a={a:.3}:2a={a:.3}:0b={b:.3}:1b={b:.3}:2b={b:.3}:s={frames}")
+            }
+            StabilizeMethod::Hqdn3d => {
+                let t = 1.0 + 9.0 * s;
+                format!("hqdn3d=0:0:{t:.1}:{t:.1}")
+            }
+            StabilizeMethod::Tmix => {
+                // Full strength weighs the neighbours like the current frame.
+                let centre = 1.0 + 8.0 * (1.0 - s);
+                format!("tmix=frames=3:weights=1 {centre:.2} 1")
+            }
+            StabilizeMethod::Deflicker => {
+                let size = 3 + 2 * (3.0 * s).round() as u32;
+                format!("deflicker=size={size}:mode=pm")
+            }
+        })
+    }
+}
+
+/// Runs the upscaled sequence through the temporal filter into `output_dir`, keeping
+/// frame count and numbering, and returns the new pattern. Each scene delimited by
+/// `scene_cuts` (indices of the first frame of every new scene) is filtered separately.
+/// Why: Single-image models process every frame independently, so fine texture
+/// shimmers; smoothing before the encode also saves bitrate on that noise. Averaging
+/// across a cut would ghost the previous scene into the next one.
+pub fn stabilize_frames(
+    input_pattern: &Path,
+    output_dir: &Path,
+    rate: FrameRate,
+    opts: &TemporalOptions,
+    scene_cuts: &[usize],
+) -> Result<PathBuf, PixyError> {
+    let filter = opts.filter()?;
+    std::fs::create_dir_all(output_dir)?;
+    let ext = input_pattern
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".into());
+    let pattern = output_dir.join(format!("%08d.{ext}"));
+    let frame_count = list_sequence(input_pattern)?.len();
+
+    for scene in split_scenes(scene_cuts, frame_count) {
+        // Frames are numbered from 1.
+        let start = (scene.start + 1).to_string();
+        let args = vec![
+            "-y".into(),
+            "-framerate".into(),
+            rate.to_string(),
+            "-start_number".into(),
+            start.clone(),
+            "-i".into(),
+            input_pattern.to_string_lossy().to_string(),
+            "-frames:v".into(),
+            scene.len().to_string(),
+            "-vf".into(),
+            filter.clone(),
+            "-vsync".into(),
+            "0".into(),
+            "-start_number".into(),
+            start,
+            pattern.to_string_lossy().to_string(),
+        ];
+        run_ffmpeg(&args)?;
+    }
+    Ok(pattern)
+}
@@ -0,0 +1,413 @@
+//! Test doubles for exercising the pipeline without ffmpeg, ncnn binaries or a GPU.
+//! Enabled with the `test-support` feature.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use image::imageops::{self, FilterType};
+
+use crate::error::PixyError;
+use crate::models::ModelSpec;
+use crate::upscalers::cpu::{image_error, list_sequence};
+use crate::upscalers::{
+    validate_request, BackendCapabilities, UpscaleRequest, UpscaledFormat, UpscalerBackend,
+    UpscalerEvent, UpscalerOptions,
+};
+
+/// One recorded call to `MockUpscaler::run`.
+#[derive(Debug, Clone)]
+pub struct MockInvocation {
+    pub input_pattern: PathBuf,
+    pub output_pattern: PathBuf,
+    pub gpu: usize,
+    pub options: UpscalerOptions,
+    pub model: ModelSpec,
+    /// Frames found in the input directory, in processing order.
+    pub frames: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    invocations: Vec<MockInvocation>,
+    oom_above_tile: Option<u32>,
+    no_tile_option: bool,
+}
+
+/// Deterministic backend that scales frames with nearest-neighbour and records every call.
+/// Clones share their recorded state, so a test can keep one and hand another to a job.
+#[derive(Debug, Clone, Default)]
+pub struct MockUpscaler {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockUpscaler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Simulates a GPU out-of-memory failure whenever the tile size is automatic or
+    /// larger than `limit`: the first half of the frames is written, then the run fails
+    /// with an ncnn-style `vkAllocateMemory failed` log.
+    pub fn fail_when_tile_above(&self, limit: u32) {
+        self.lock().oom_above_tile = Some(limit);
+    }
+
+    /// Declares no tile size option, like a template backend without `{tile}`.
+    pub fn without_tile_option(&self) {
+        self.lock().no_tile_option = true;
+    }
+
+    /// Calls recorded so far.
+    pub fn invocations(&self) -> Vec<MockInvocation> {
+        self.lock().invocations.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl UpscalerBackend for MockUpscaler {
+    fn id(&self) -> &str {
+        "mock"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            tile_size: !self.lock().no_tile_option,
+            thread_tuning: true,
+            tta: true,
+            gpu_selection: true,
+            high_bit_depth: false,
+            output_formats: vec![UpscaledFormat::Png],
+        }
+    }
+
+    fn supported_scales(&self, _model: &ModelSpec) -> Vec<u32> {
+        (1..=8).collect()
+    }
+
+    fn supported_denoise(&self, _model: &ModelSpec) -> Vec<u8> {
+        vec![0, 1, 2, 3]
+    }
+
+    fn find_binary(&self) -> Result<PathBuf, PixyError> {
+        Ok(PathBuf::from("mock-upscaler"))
+    }
+
+    fn build_args(&self, request: &UpscaleRequest<'_>) -> Result<Vec<OsString>, PixyError> {
+        Ok(vec![
+            request.input_pattern.into(),
+            request.output_pattern.into(),
+        ])
+    }
+
+    fn run(
+        &self,
+        request: &UpscaleRequest<'_>,
+        on_event: &mut dyn FnMut(UpscalerEvent),
+    ) -> Result<(), PixyError> {
+        validate_request(self, request)?;
+        let frames = list_sequence(request.input_pattern)?;
+        let oom_limit = {
+            let mut state = self.lock();
+            state.invocations.push(MockInvocation {
+                input_pattern: request.input_pattern.to_path_buf(),
+                output_pattern: request.output_pattern.to_path_buf(),
+                gpu: request.gpu,
+                options: request.options.clone(),
+                model: request.model.clone(),
+                frames: frames.clone(),
+            });
+            state.oom_above_tile
+        };
+        let tile = request.options.tile_size.unwrap_or(0);
+        let oom = oom_limit.is_some_and(|limit| tile == 0 || tile > limit);
+
+        let output_dir = request.output_pattern.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(output_dir)?;
+        let scale = request.model.scale;
+        let count = if oom { frames.len() / 2 } else { frames.len() };
+        for input in frames.iter().take(count) {
+            let img = image::open(input).map_err(image_error)?.to_rgba8();
+            let scaled = imageops::resize(
+                &img,
+                img.width() * scale,
+                img.height() * scale,
+                FilterType::Nearest,
+            );
+            let output = output_dir
+                .join(input.file_stem().unwrap_or_default())
+                .with_extension("png");
+            scaled.save(&output).map_err(image_error)?;
+            on_event(UpscalerEvent::FileDone {
+                input: input.clone(),
+                output,
+            });
+        }
+
+        if oom {
+            return Err(PixyError::ProcessFailed {
+                cmd: "mock-upscaler".into(),
+                code: Some(255),
+                stderr: "vkAllocateMemory failed".into(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// ffprobe output served by the fake `ffprobe` unless overridden: one 24 fps
+/// 64x48 video stream and one AAC audio stream, 1 second long.
+pub const DEFAULT_PROBE_JSON: &str = r#"{
+  "streams": [
+    {"index": 0, "codec_type": "video", "codec_name": "h264", "width": 64, "height": 48,
+     "pix_fmt": "yuv420p", "r_frame_rate": "24/1", "avg_frame_rate": "24/1"},
+    {"index": 1, "codec_type": "audio", "codec_name": "aac", "channels": 2,
+     "tags": {"language": "eng"}}
+  ],
+  "format": {"format_name": "matroska,webm", "duration": "1.000000", "size": "1000"}
+}"#;
+
+/// Encoders the fake `ffmpeg -encoders` lists unless overridden: every `EncoderKind`.
+pub const DEFAULT_ENCODERS: &[&str] = &[
+    "h264_nvenc",
+    "hevc_nvenc",
+    "av1_nvenc",
+    "h264_amf",
+    "hevc_amf",
+    "av1_amf",
+    "h264_qsv",
+    "hevc_qsv",
+    "av1_qsv",
+    "h264_vaapi",
+    "hevc_vaapi",
+    "av1_vaapi",
+    "libx264",
+    "libx265",
+    "libsvtav1",
+    "libaom-av1",
+    "libvpx-vp9",
+    "prores_ks",
+    "ffv1",
+];
+
+/// `loudnorm` measurement the fake `ffmpeg` prints for analysis runs unless overridden.
+pub const DEFAULT_LOUDNORM_JSON: &str = r#"{
+  "input_i" : "-31.20",
+  "input_tp" : "-9.80",
+  "input_lra" : "5.40",
+  "input_thresh" : "-41.70",
+  "target_offset" : "0.30"
+}"#;
+
+#[cfg(unix)]
+const FAKE_FFMPEG: &str = r#"#!/bin/sh
+dir="$(dirname "$0")"
+for a; do [ "$a" = "-encoders" ] && { cat "$dir/encoders"; exit 0; }; done
+{ for a in "$@"; do printf '%s\n' "$a"; done; printf '%s\n' '---'; } >> "$dir/ffmpeg.log"
+if [ -f "$dir/ffmpeg.fail" ]; then cat "$dir/ffmpeg.fail" >&2; exit 1; fi
+case "$*" in
+  *print_format=json*)
+    { echo '[Parsed_loudnorm_0 @ 0x0]'; cat "$dir/loudnorm.json"; } >&2 ;;
+esac
+for last; do :; done
+case "$last" in
+  *%*)
+    n=$(cat "$dir/frames"); i=1
+    while [ "$i" -le "$n" ]; do
+      src="$dir/frame$i.png"; [ -f "$src" ] || src="$dir/frame.png"
+      cp "$src" "$(printf "$last" "$i")"; i=$((i + 1))
+    done ;;
+  -) printf 'fake' ;;
+  *) printf 'fake' > "$last" ;;
+esac
+"#;
+
+#[cfg(unix)]
+const FAKE_FFPROBE: &str = r#"#!/bin/sh
+dir="$(dirname "$0")"
+{ for a in "$@"; do printf '%s\n' "$a"; done; printf '%s\n' '---'; } >> "$dir/ffprobe.log"
+cat "$dir/probe.json"
+"#;
+
+#[cfg(unix)]
+const FAKE_RIFE: &str = r#"#!/bin/sh
+dir="$(dirname "$0")"
+{ for a in "$@"; do printf '%s\n' "$a"; done; printf '%s\n' '---'; } >> "$dir/rife.log"
+out=.; n=0
+while [ $# -gt 0 ]; do
+  case "$1" in -o) out="$2"; shift ;; -n) n="$2"; shift ;; esac
+  shift
+done
+i=1
+while [ "$i" -le "$n" ]; do cp "$dir/frame.png" "$(printf '%s/%08d.png' "$out" "$i")"; i=$((i + 1)); done
+"#;
+
+#[cfg(unix)]
+static TOOLS_LOCK: Mutex<()> = Mutex::new(());
+#[cfg(unix)]
+static TOOLS_COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// Stub `ffmpeg`/`ffprobe` scripts installed into a scratch directory that is put first
+/// on `PATH` (and `PIXY_UPPY_CACHE_DIR`, so tile caches stay isolated).
+///
+/// The fake `ffmpeg` logs its arguments, writes `frame_count` copies of a small test
+/// frame when the last argument is a printf-style pattern, and otherwise creates the
+/// output file; `set_extracted_frame` replaces single extracted frames. `ffmpeg
+/// -encoders` queries are answered from `set_encoders` without being logged, so
+/// capability checks do not show up in `ffmpeg_calls`. `loudnorm` analysis runs print
+/// `DEFAULT_LOUDNORM_JSON` or `set_loudnorm_json`. The fake `ffprobe` prints
+/// `DEFAULT_PROBE_JSON` or `set_probe_json`. The fake `rife-ncnn-vulkan` logs its
+/// arguments and writes `-n` copies of the test frame into the `-o` directory.
+///
+/// Because the environment is process-global, holding a `FakeTools` serialises all
+/// tests that use one. Unix only.
+#[cfg(unix)]
+pub struct FakeTools {
+    dir: PathBuf,
+    path: Option<OsString>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+#[cfg(unix)]
+impl FakeTools {
+    /// Writes the stubs and puts their directory first on `PATH`.
+    pub fn install() -> Result<Self, PixyError> {
+        use std::os::unix::fs::PermissionsExt;
+        use std::sync::atomic::Ordering;
+
+        use image::{Rgb, RgbImage};
+
+        let guard = TOOLS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!(
+            "pixy-uppy-fake-{}-{}",
+            std::process::id(),
+            TOOLS_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::create_dir_all(&dir)?;
+
+        for (name, body) in [
+            ("ffmpeg", FAKE_FFMPEG),
+            ("ffprobe", FAKE_FFPROBE),
+            ("rife-ncnn-vulkan", FAKE_RIFE),
+        ] {
+            let path = dir.join(name);
+            std::fs::write(&path, body)?;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        RgbImage::from_fn(16, 12, |x, y| Rgb([(x * 16) as u8, (y * 20) as u8, 128]))
+            .save(dir.join("frame.png"))
+            .map_err(image_error)?;
+
+        let tools = Self {
+            dir,
+            path: std::env::var_os("PATH"),
+            _guard: guard,
+        };
+        tools.set_frame_count(3)?;
+        tools.set_probe_json(DEFAULT_PROBE_JSON)?;
+        tools.set_loudnorm_json(DEFAULT_LOUDNORM_JSON)?;
+        tools.set_encoders(DEFAULT_ENCODERS)?;
+        let mut dirs = vec![tools.dir.clone()];
+        dirs.extend(tools.path.iter().flat_map(std::env::split_paths));
+        std::env::set_var(
+            "PATH",
+            std::env::join_paths(dirs).map_err(std::io::Error::other)?,
+        );
+        std::env::set_var("PIXY_UPPY_CACHE_DIR", tools.dir.join("cache"));
+        Ok(tools)
+    }
+
+    /// Directory holding the stubs; also a convenient scratch area for a test.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Number of frames the fake `ffmpeg` writes when extracting.
+    pub fn set_frame_count(&self, count: usize) -> Result<(), PixyError> {
+        Ok(std::fs::write(self.dir.join("frames"), count.to_string())?)
+    }
+
+    /// Writes `frame` as extracted frame `index` (1-based) instead of the test frame.
+    pub fn set_extracted_frame(
+        &self,
+        index: usize,
+        frame: &image::RgbImage,
+    ) -> Result<(), PixyError> {
+        frame
+            .save(self.dir.join(format!("frame{index}.png")))
+            .map_err(image_error)
+    }
+
+    /// JSON the fake `ffprobe` prints.
+    pub fn set_probe_json(&self, json: &str) -> Result<(), PixyError> {
+        Ok(std::fs::write(self.dir.join("probe.json"), json)?)
+    }
+
+    /// Measurement the fake `ffmpeg` prints for `loudnorm` analysis runs.
+    pub fn set_loudnorm_json(&self, json: &str) -> Result<(), PixyError> {
+        Ok(std::fs::write(self.dir.join("loudnorm.json"), json)?)
+    }
+
+    /// Video encoders the fake `ffmpeg -encoders` lists.
+    pub fn set_encoders(&self, names: &[&str]) -> Result<(), PixyError> {
+        let mut list = String::from("Encoders:\n V..... = Video\n ------\n");
+        for name in names {
+            list.push_str(&format!(" V....D {name:<20} fake encoder\n"));
+        }
+        Ok(std::fs::write(self.dir.join("encoders"), list)?)
+    }
+
+    /// Makes every following `ffmpeg` call fail with `stderr`; `None` restores success.
+    pub fn fail_ffmpeg(&self, stderr: Option<&str>) -> Result<(), PixyError> {
+        let marker = self.dir.join("ffmpeg.fail");
+        match stderr {
+            Some(msg) => std::fs::write(marker, msg)?,
+            None if marker.exists() => std::fs::remove_file(marker)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Arguments of every `ffmpeg` call so far, in order.
+    pub fn ffmpeg_calls(&self) -> Vec<Vec<String>> {
+        read_calls(&self.dir.join("ffmpeg.log"))
+    }
+
+    /// Arguments of every `ffprobe` call so far, in order.
+    pub fn ffprobe_calls(&self) -> Vec<Vec<String>> {
+        read_calls(&self.dir.join("ffprobe.log"))
+    }
+
+    /// Arguments of every `rife-ncnn-vulkan` call so far, in order.
+    pub fn rife_calls(&self) -> Vec<Vec<String>> {
+        read_calls(&self.dir.join("rife.log"))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for FakeTools {
+    fn drop(&mut self) {
+        match &self.path {
+            Some(path) => std::env::set_var("PATH", path),
+            None => std::env::remove_var("PATH"),
+        }
+        std::env::remove_var("PIXY_UPPY_CACHE_DIR");
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(unix)]
+fn read_calls(log: &Path) -> Vec<Vec<String>> {
+    let Ok(text) = std::fs::read_to_string(log) else {
+        return Vec::new();
+    };
+    text.split_terminator("---\n")
+        .map(|call| call.lines().map(str::to_string).collect())
+        .collect()
+}
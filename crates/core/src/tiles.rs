@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::error::PixyError;
+
+/// Smallest tile size accepted by the ncnn upscalers.
+pub const MIN_TILE_SIZE: u32 = 32;
+
+/// Tile size tried first when the binary's automatic choice (`0`) runs out of memory.
+const FIRST_EXPLICIT_TILE_SIZE: u32 = 256;
+
+/// Returns the next smaller tile size to try after an out-of-memory failure,
+/// or `None` when already at the minimum.
+/// Why: Halving converges quickly while keeping tiles large enough to stay efficient.
+pub fn next_tile_size(current: u32) -> Option<u32> {
+    let next = if current == 0 {
+        FIRST_EXPLICIT_TILE_SIZE
+    } else {
+        current / 2
+    };
+    (next >= MIN_TILE_SIZE).then_some(next)
+}
+
+/// Persistent record of tile sizes known to work per GPU and model.
+/// Why: Saves future runs from repeating the out-of-memory retries.
+#[derive(Debug, Default)]
+pub struct TileCache {
+    path: Option<PathBuf>,
+    entries: BTreeMap<String, u32>,
+}
+
+impl TileCache {
+    /// Loads the cache from the user cache directory. A missing or unreadable
+    /// file yields an empty cache.
+    pub fn load() -> Self {
+        let path = cache_file();
+        let entries = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self { path, entries }
+    }
+
+    /// Returns the remembered tile size for a device/model pair.
+    pub fn get(&self, device: &str, model: &str) -> Option<u32> {
+        self.entries.get(&key(device, model)).copied()
+    }
+
+    /// Records a working tile size and writes the cache back to disk.
+    pub fn remember(&mut self, device: &str, model: &str, tile: u32) -> Result<(), PixyError> {
+        self.entries.insert(key(device, model), tile);
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, serde_json::to_vec_pretty(&self.entries)?)?;
+        }
+        Ok(())
+    }
+}
+
+fn key(device: &str, model: &str) -> String {
+    format!("{device}|{model}")
+}
+
+/// Location of the tile cache: `PIXY_UPPY_CACHE_DIR` or the platform cache directory.
+fn cache_file() -> Option<PathBuf> {
+    std::env::var_os("PIXY_UPPY_CACHE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| dirs::cache_dir().map(|d| d.join("pixy-uppy")))
+        .map(|d| d.join("tile_sizes.json"))
+}
@@ -0,0 +1,439 @@
+use std::f32::consts::PI;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, ImageBuffer, Rgba};
+
+use crate::devices::VulkanDevice;
+use crate::error::PixyError;
+use crate::models::ModelSpec;
+
+use super::{
+    validate_request, BackendCapabilities, UpscaleRequest, UpscaledFormat, UpscalerBackend,
+    UpscalerEvent,
+};
+
+type Rgba32F = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+/// Resampling kernel used by the CPU backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleFilter {
+    /// Separable Lanczos, 3 lobes: sharp, slight ringing.
+    Lanczos3,
+    /// Separable Catmull-Rom bicubic: softer, no visible ringing.
+    Bicubic,
+    /// Elliptical weighted average with a jinc-windowed jinc kernel: fewer jaggies on diagonals.
+    EwaLanczos,
+}
+
+impl ResampleFilter {
+    fn radius(self) -> f32 {
+        match self {
+            ResampleFilter::Lanczos3 => 3.0,
+            ResampleFilter::Bicubic => 2.0,
+            // Third zero of jinc, the usual 3-lobe EWA Lanczos support.
+            ResampleFilter::EwaLanczos => 3.238_315_5,
+        }
+    }
+}
+
+/// Pure-Rust resampling backend used when no usable GPU or ncnn binary is available.
+/// Why: Keeps the pipeline (and tests) working on CPU-only machines and CI.
+#[derive(Debug, Clone)]
+pub struct CpuResampler {
+    pub filter: ResampleFilter,
+    /// Edge-directed sharpening amount; `0.0` disables it.
+    pub sharpen: f32,
+}
+
+impl Default for CpuResampler {
+    fn default() -> Self {
+        Self {
+            filter: ResampleFilter::Lanczos3,
+            sharpen: 0.0,
+        }
+    }
+}
+
+impl UpscalerBackend for CpuResampler {
+    fn id(&self) -> &str {
+        "cpu"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            tile_size: false,
+            thread_tuning: false,
+            tta: false,
+            gpu_selection: false,
+            high_bit_depth: true,
+            output_formats: vec![
+                UpscaledFormat::Png,
+                UpscaledFormat::Jpg,
+                UpscaledFormat::Webp,
+            ],
+        }
+    }
+
+    fn supported_scales(&self, _model: &ModelSpec) -> Vec<u32> {
+        (1..=8).collect()
+    }
+
+    fn supported_denoise(&self, _model: &ModelSpec) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn find_binary(&self) -> Result<PathBuf, PixyError> {
+        Err(PixyError::InvalidArgument(
+            "the cpu backend runs in-process and has no binary",
+        ))
+    }
+
+    fn build_args(&self, _request: &UpscaleRequest<'_>) -> Result<Vec<OsString>, PixyError> {
+        Err(PixyError::InvalidArgument(
+            "the cpu backend runs in-process and has no command line",
+        ))
+    }
+
+    fn run(
+        &self,
+        request: &UpscaleRequest<'_>,
+        on_event: &mut dyn FnMut(UpscalerEvent),
+    ) -> Result<(), PixyError> {
+        validate_request(self, request)?;
+        let scale = request.model.scale;
+        let format = request.options.format();
+        let output_dir = request.output_pattern.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(output_dir)?;
+
+        let jobs: Vec<(PathBuf, PathBuf)> = list_sequence(request.input_pattern)?
+            .into_iter()
+            .map(|input| {
+                let stem = input.file_stem().unwrap_or_default();
+                let output = output_dir.join(stem).with_extension(format.extension());
+                (input, output)
+            })
+            .collect();
+
+        let process = |(input, output): &(PathBuf, PathBuf)| -> Result<UpscalerEvent, PixyError> {
+            self.upscale_file(input, output, scale)?;
+            Ok(UpscalerEvent::FileDone {
+                input: input.clone(),
+                output: output.clone(),
+            })
+        };
+
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            use std::sync::mpsc;
+
+            let (tx, rx) = mpsc::channel();
+            std::thread::scope(|scope| {
+                let worker = scope.spawn(move || {
+                    jobs.par_iter().try_for_each_with(tx, |tx, job| {
+                        let event = process(job)?;
+                        let _ = tx.send(event);
+                        Ok::<(), PixyError>(())
+                    })
+                });
+                for event in rx {
+                    on_event(event);
+                }
+                worker.join().expect("cpu upscale worker panicked")
+            })
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            for job in &jobs {
+                on_event(process(job)?);
+            }
+            Ok(())
+        }
+    }
+}
+
+impl CpuResampler {
+    /// Upscales a single image file by an integer factor.
+    pub fn upscale_file(&self, input: &Path, output: &Path, scale: u32) -> Result<(), PixyError> {
+        let source = image::open(input).map_err(image_error)?;
+        let has_alpha = source.color().has_alpha();
+        let deep = source.color().bytes_per_pixel() > source.color().channel_count();
+        let src = source.to_rgba32f();
+        let (w, h) = (src.width() * scale, src.height() * scale);
+
+        let mut out = match self.filter {
+            ResampleFilter::Lanczos3 | ResampleFilter::Bicubic => {
+                resize_separable(&src, w, h, self.filter)
+            }
+            ResampleFilter::EwaLanczos => resize_ewa(&src, w, h),
+        };
+        if self.sharpen > 0.0 {
+            out = sharpen_edges(&out, self.sharpen);
+        }
+
+        let out = DynamicImage::ImageRgba32F(out);
+        let is_jpeg = output
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("jpg") || e.eq_ignore_ascii_case("jpeg"));
+        // Only PNG stores 16 bits per channel; keep them for high bit depth encodes.
+        let deep = deep
+            && output
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("png"));
+        let result = match (has_alpha && !is_jpeg, deep) {
+            (true, true) => DynamicImage::ImageRgba16(out.to_rgba16()).save(output),
+            (true, false) => DynamicImage::ImageRgba8(out.to_rgba8()).save(output),
+            (false, true) => DynamicImage::ImageRgb16(out.to_rgb16()).save(output),
+            (false, false) => DynamicImage::ImageRgb8(out.to_rgb8()).save(output),
+        };
+        result.map_err(image_error)
+    }
+}
+
+/// Why the CPU resampler has to stand in for `upscaler`: no hardware Vulkan device among
+/// `devices` (only llvmpipe/lavapipe/SwiftShader), or no binary for the backend. `None`
+/// when the GPU path can run.
+pub fn cpu_fallback_reason(
+    devices: &[VulkanDevice],
+    upscaler: &dyn UpscalerBackend,
+) -> Option<String> {
+    if !devices.iter().any(|d| !d.is_software()) {
+        return Some("no hardware Vulkan device found".into());
+    }
+    if upscaler.find_binary().is_err() {
+        return Some(format!("{} binary not found", upscaler.id()));
+    }
+    None
+}
+
+/// Lists the files of an image sequence: every file in the pattern's directory with
+/// the pattern's extension, sorted by name.
+pub(crate) fn list_sequence(pattern: &Path) -> Result<Vec<PathBuf>, PixyError> {
+    let dir = pattern.parent().unwrap_or(Path::new("."));
+    let ext = pattern.extension();
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension() == ext)
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+pub(crate) fn image_error(err: image::ImageError) -> PixyError {
+    match err {
+        image::ImageError::IoError(e) => PixyError::Io(e),
+        other => PixyError::Io(std::io::Error::other(other)),
+    }
+}
+
+fn kernel(filter: ResampleFilter, x: f32) -> f32 {
+    let x = x.abs();
+    match filter {
+        ResampleFilter::Lanczos3 => {
+            if x < 1e-6 {
+                1.0
+            } else if x < 3.0 {
+                let px = PI * x;
+                3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+            } else {
+                0.0
+            }
+        }
+        ResampleFilter::Bicubic => {
+            // Catmull-Rom (B = 0, C = 0.5).
+            if x < 1.0 {
+                1.5 * x * x * x - 2.5 * x * x + 1.0
+            } else if x < 2.0 {
+                -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+            } else {
+                0.0
+            }
+        }
+        ResampleFilter::EwaLanczos => {
+            let r = ResampleFilter::EwaLanczos.radius();
+            if x < r {
+                // Window the jinc so its first zero lands on the support radius.
+                jinc(x) * jinc(x * 1.219_67 / r)
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+/// `2·J1(πx)/(πx)`, normalised so `jinc(0) = 1`.
+fn jinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        let px = PI * x;
+        2.0 * bessel_j1(px) / px
+    }
+}
+
+/// Bessel function of the first kind, order one (rational approximation, |err| < 1e-7).
+fn bessel_j1(x: f32) -> f32 {
+    let x = f64::from(x);
+    let ax = x.abs();
+    let result = if ax < 8.0 {
+        let y = x * x;
+        let num = x
+            * (72_362_614_232.0
+                + y * (-7_895_059_235.0
+                    + y * (242_396_853.1
+                        + y * (-2_972_611.439 + y * (15_704.482_60 + y * -30.160_366_06)))));
+        let den = 144_725_228_442.0
+            + y * (2_300_535_178.0
+                + y * (18_583_304.74 + y * (99_447.433_94 + y * (376.999_139_7 + y))));
+        num / den
+    } else {
+        let z = 8.0 / ax;
+        let y = z * z;
+        let xx = ax - 2.356_194_491;
+        let p = 1.0
+            + y * (0.183_105e-2
+                + y * (-0.351_639_649_6e-4 + y * (0.245_752_017_3e-5 + y * -0.240_337_019e-6)));
+        let q = 0.046_874_999_95
+            + y * (-0.200_269_087_3e-3
+                + y * (0.844_919_909_3e-5 + y * (-0.882_289_87e-6 + y * 0.105_787_412e-6)));
+        let v = (std::f64::consts::FRAC_2_PI / ax).sqrt() * (xx.cos() * p - z * xx.sin() * q);
+        if x < 0.0 {
+            -v
+        } else {
+            v
+        }
+    };
+    result as f32
+}
+
+/// Per-output-coordinate taps: first source index and normalised weights.
+fn weights(src_len: u32, dst_len: u32, filter: ResampleFilter) -> Vec<(i64, Vec<f32>)> {
+    let ratio = src_len as f32 / dst_len as f32;
+    // Widen the kernel when downscaling so it still low-passes.
+    let support = ratio.max(1.0);
+    let radius = filter.radius() * support;
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * ratio - 0.5;
+            let first = (center - radius).floor() as i64 + 1;
+            let last = (center + radius).floor() as i64;
+            let mut taps: Vec<f32> = (first..=last)
+                .map(|j| kernel(filter, (j as f32 - center) / support))
+                .collect();
+            let sum: f32 = taps.iter().sum();
+            if sum.abs() > 1e-6 {
+                taps.iter_mut().for_each(|w| *w /= sum);
+            }
+            (first, taps)
+        })
+        .collect()
+}
+
+fn resize_separable(src: &Rgba32F, w: u32, h: u32, filter: ResampleFilter) -> Rgba32F {
+    let (sw, sh) = src.dimensions();
+    let clamp = |v: i64, len: u32| v.clamp(0, i64::from(len) - 1) as u32;
+
+    let horizontal = weights(sw, w, filter);
+    let mut tmp: Rgba32F = ImageBuffer::new(w, sh);
+    for y in 0..sh {
+        for (x, (first, taps)) in horizontal.iter().enumerate() {
+            let mut acc = [0.0f32; 4];
+            for (k, weight) in taps.iter().enumerate() {
+                let p = src.get_pixel(clamp(first + k as i64, sw), y).0;
+                (0..4).for_each(|c| acc[c] += p[c] * weight);
+            }
+            tmp.put_pixel(x as u32, y, Rgba(acc));
+        }
+    }
+
+    let vertical = weights(sh, h, filter);
+    let mut out: Rgba32F = ImageBuffer::new(w, h);
+    for (y, (first, taps)) in vertical.iter().enumerate() {
+        for x in 0..w {
+            let mut acc = [0.0f32; 4];
+            for (k, weight) in taps.iter().enumerate() {
+                let p = tmp.get_pixel(x, clamp(first + k as i64, sh)).0;
+                (0..4).for_each(|c| acc[c] += p[c] * weight);
+            }
+            out.put_pixel(x, y as u32, Rgba(acc.map(|v| v.clamp(0.0, 1.0))));
+        }
+    }
+    out
+}
+
+fn resize_ewa(src: &Rgba32F, w: u32, h: u32) -> Rgba32F {
+    let (sw, sh) = src.dimensions();
+    let rx = sw as f32 / w as f32;
+    let ry = sh as f32 / h as f32;
+    let radius = ResampleFilter::EwaLanczos.radius();
+    let reach = radius.ceil() as i64;
+    let mut out: Rgba32F = ImageBuffer::new(w, h);
+    for y in 0..h {
+        let cy = (y as f32 + 0.5) * ry - 0.5;
+        for x in 0..w {
+            let cx = (x as f32 + 0.5) * rx - 0.5;
+            let (bx, by) = (cx.floor() as i64, cy.floor() as i64);
+            let mut acc = [0.0f32; 4];
+            let mut total = 0.0f32;
+            for j in (by - reach + 1)..=(by + reach) {
+                for i in (bx - reach + 1)..=(bx + reach) {
+                    let d = ((i as f32 - cx).powi(2) + (j as f32 - cy).powi(2)).sqrt();
+                    if d >= radius {
+                        continue;
+                    }
+                    let weight = kernel(ResampleFilter::EwaLanczos, d);
+                    let sx = i.clamp(0, i64::from(sw) - 1) as u32;
+                    let sy = j.clamp(0, i64::from(sh) - 1) as u32;
+                    let p = src.get_pixel(sx, sy).0;
+                    (0..4).for_each(|c| acc[c] += p[c] * weight);
+                    total += weight;
+                }
+            }
+            let px = acc.map(|v| (v / total).clamp(0.0, 1.0));
+            out.put_pixel(x, y, Rgba(px));
+        }
+    }
+    out
+}
+
+/// Unsharp mask scaled by local luma gradient, so edges are crisped while flat
+/// areas (where sharpening only amplifies noise) are left mostly untouched.
+fn sharpen_edges(img: &Rgba32F, amount: f32) -> Rgba32F {
+    let (w, h) = img.dimensions();
+    let at = |x: i64, y: i64| {
+        let x = x.clamp(0, i64::from(w) - 1) as u32;
+        let y = y.clamp(0, i64::from(h) - 1) as u32;
+        img.get_pixel(x, y).0
+    };
+    let luma = |p: [f32; 4]| 0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2];
+    let mut out = img.clone();
+    for y in 0..i64::from(h) {
+        for x in 0..i64::from(w) {
+            let mut blur = [0.0f32; 4];
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let p = at(x + dx, y + dy);
+                    (0..4).for_each(|c| blur[c] += p[c] / 9.0);
+                }
+            }
+            let gx = luma(at(x + 1, y - 1)) + 2.0 * luma(at(x + 1, y)) + luma(at(x + 1, y + 1))
+                - luma(at(x - 1, y - 1))
+                - 2.0 * luma(at(x - 1, y))
+                - luma(at(x - 1, y + 1));
+            let gy = luma(at(x - 1, y + 1)) + 2.0 * luma(at(x, y + 1)) + luma(at(x + 1, y + 1))
+                - luma(at(x - 1, y - 1))
+                - 2.0 * luma(at(x, y - 1))
+                - luma(at(x + 1, y - 1));
+            let edge = ((gx * gx + gy * gy).sqrt() / 0.5).min(1.0);
+            let p = at(x, y);
+            let mut px = p;
+            // Leave alpha alone; sharpening it creates halos around cut-outs.
+            (0..3).for_each(|c| {
+                px[c] = (p[c] + amount * edge * (p[c] - blur[c])).clamp(0.0, 1.0);
+            });
+            out.put_pixel(x as u32, y as u32, Rgba(px));
+        }
+    }
+    out
+}
@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::error::PixyError;
+
+/// Number of trailing log lines kept for error reports.
+const LOG_TAIL_LINES: usize = 40;
+
+static PERCENT_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*(\d{1,3}(?:\.\d+)?)%\s*$").expect("valid regex"));
+static DONE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(.+?) -> (.+?) done\s*$").expect("valid regex"));
+
+/// Structured event parsed from a line of upscaler output.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpscalerEvent {
+    /// Completion of the image currently being processed, in percent.
+    Progress { percent: f32 },
+    /// An image finished processing (printed by the ncnn tools in verbose mode).
+    FileDone { input: PathBuf, output: PathBuf },
+    /// Any other line, passed through for logging.
+    Log(String),
+}
+
+/// Known failure causes recognised in ncnn upscaler logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscalerFailure {
+    /// The GPU ran out of memory; a smaller tile size usually helps.
+    OutOfMemory,
+    /// The model `.param`/`.bin` files could not be found or loaded.
+    MissingModel,
+    /// The selected GPU is missing or cannot run the Vulkan compute path.
+    UnsupportedDevice,
+}
+
+impl UpscalerFailure {
+    /// Classifies a failure from upscaler log output.
+    /// Why: ncnn tools exit with generic codes; the log is the only reliable signal.
+    pub fn classify(log: &str) -> Option<Self> {
+        let lower = log.to_ascii_lowercase();
+        let any = |needles: &[&str]| needles.iter().any(|n| lower.contains(n));
+        if any(&[
+            "vkallocatememory failed",
+            "out_of_device_memory",
+            "out of device memory",
+            "out_of_host_memory",
+            "vkmapmemory failed",
+        ]) {
+            Some(UpscalerFailure::OutOfMemory)
+        } else if any(&[
+            "invalid gpu device",
+            "no vulkan device",
+            "vkcreateinstance failed",
+            "vkcreatedevice failed",
+            "gpu device count is 0",
+        ]) {
+            Some(UpscalerFailure::UnsupportedDevice)
+        } else if any(&[
+            "network graph not ready",
+            "param is too old",
+            "load_param",
+            "load_model",
+        ]) || lower.lines().any(|l| {
+            // ncnn logs `fopen <path> failed` for models, but unreadable frames fail in
+            // fopen too; only the model files end in `.param`/`.bin`.
+            l.contains("fopen") && (l.contains(".param") || l.contains(".bin"))
+        }) {
+            Some(UpscalerFailure::MissingModel)
+        } else {
+            None
+        }
+    }
+
+    /// Short human readable description.
+    pub fn describe(self) -> &'static str {
+        match self {
+            UpscalerFailure::OutOfMemory => "GPU out of memory (try a smaller --tile-size)",
+            UpscalerFailure::MissingModel => "model files missing or failed to load",
+            UpscalerFailure::UnsupportedDevice => "GPU device missing or not supported",
+        }
+    }
+}
+
+/// Parses a single line printed by an ncnn upscaler.
+pub fn parse_line(line: &str) -> UpscalerEvent {
+    if let Some(caps) = PERCENT_RE.captures(line) {
+        if let Ok(percent) = caps[1].parse::<f32>() {
+            return UpscalerEvent::Progress { percent };
+        }
+    }
+    if let Some(caps) = DONE_RE.captures(line) {
+        return UpscalerEvent::FileDone {
+            input: PathBuf::from(&caps[1]),
+            output: PathBuf::from(&caps[2]),
+        };
+    }
+    UpscalerEvent::Log(line.to_string())
+}
+
+/// Runs an upscaler command, streaming stdout/stderr through `parse` into `on_event`.
+/// On failure the tail of the combined log is returned in `PixyError::ProcessFailed`.
+pub(crate) fn run_streaming(
+    cmd: &mut Command,
+    parse: &dyn Fn(&str) -> UpscalerEvent,
+    on_event: &mut dyn FnMut(UpscalerEvent),
+) -> Result<(), PixyError> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let (tx, rx) = mpsc::channel::<String>();
+    let readers = [
+        child.stdout.take().map(|s| spawn_reader(s, tx.clone())),
+        child.stderr.take().map(|s| spawn_reader(s, tx.clone())),
+    ];
+    drop(tx);
+
+    let mut tail: VecDeque<String> = VecDeque::with_capacity(LOG_TAIL_LINES);
+    for line in rx {
+        let event = parse(&line);
+        // Progress lines are too chatty to be useful in error reports.
+        if !matches!(event, UpscalerEvent::Progress { .. }) {
+            if tail.len() == LOG_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+        on_event(event);
+    }
+    for reader in readers.into_iter().flatten() {
+        let _ = reader.join();
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(PixyError::ProcessFailed {
+            cmd: format!("{:?}", cmd),
+            code: status.code(),
+            stderr: Vec::from(tail).join("\n"),
+        });
+    }
+    Ok(())
+}
+
+fn spawn_reader<R: Read + Send + 'static>(
+    stream: R,
+    tx: mpsc::Sender<String>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        // ncnn tools may emit non-UTF-8 paths; decode lossily line by line.
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+        while let Ok(n) = reader.read_until(b'\n', &mut buf) {
+            if n == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if !line.is_empty() && tx.send(line.to_string()).is_err() {
+                break;
+            }
+            buf.clear();
+        }
+    })
+}
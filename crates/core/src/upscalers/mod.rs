@@ -1,111 +1,205 @@
 use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
 
 use crate::error::PixyError;
-use crate::models::ModelSpec;
-use crate::paths::resolve_tool;
+use crate::models::{ModelKind, ModelSpec};
 
-#[derive(Debug, Clone, Copy)]
-pub enum UpscalerKind {
-    RealEsrgan,
-    RealCugan,
-    Waifu2x,
+pub mod cpu;
+pub mod log;
+pub mod ncnn;
+pub mod template;
+
+pub use cpu::{cpu_fallback_reason, CpuResampler, ResampleFilter};
+pub use log::{UpscalerEvent, UpscalerFailure};
+pub use ncnn::{RealCugan, RealEsrgan, Waifu2x};
+pub use template::{TemplateBackend, TemplateSpec};
+
+/// Image format written by the ncnn upscalers (`-f`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaledFormat {
+    Png,
+    Jpg,
+    Webp,
 }
 
-/// Represents an upscaler binary by kind and path on disk.
-#[derive(Debug, Clone)]
-pub struct UpscalerBinary {
-    pub kind: UpscalerKind,
-    pub path: PathBuf,
+impl UpscaledFormat {
+    /// File extension (and `-f` value) for this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            UpscaledFormat::Png => "png",
+            UpscaledFormat::Jpg => "jpg",
+            UpscaledFormat::Webp => "webp",
+        }
+    }
 }
 
-impl UpscalerBinary {
-    /// Runs the upscaler on an image sequence, writing an output sequence.
-    /// Why: We isolate invocation details and flags per binary in one place.
-    pub fn run(
-        &self,
-        input_pattern: &Path,
-        output_pattern: &Path,
-        gpu: usize,
-        tile_size: Option<u32>,
-        threads: Option<u32>,
-        model: &ModelSpec,
-    ) -> Result<(), PixyError> {
-        let mut cmd = match self.kind {
-            UpscalerKind::RealEsrgan => {
-                let mut c = Command::new(&self.path);
-                let input_arg = sequence_dir_arg(input_pattern);
-                let output_arg = sequence_dir_arg(output_pattern);
-                c.args([
-                    "-i",
-                    input_arg.as_ref(),
-                    "-o",
-                    output_arg.as_ref(),
-                    "-n",
-                    &model.name,
-                    "-g",
-                ])
-                .arg(gpu.to_string());
-                if let Some(t) = tile_size {
-                    c.args(["-t", &t.to_string()]);
-                }
-                if let Some(th) = threads {
-                    c.args(["-j", &format!("{}:{}:{}", th, th, th)]);
-                }
-                c
+/// Tuning options forwarded to the upscaler binary.
+/// Why: Users need tile/thread/TTA control to fit VRAM and trade speed for quality.
+#[derive(Debug, Clone, Default)]
+pub struct UpscalerOptions {
+    /// Tile size (`-t`); `0` lets the binary pick automatically.
+    pub tile_size: Option<u32>,
+    /// Image decode threads (first field of `-j load:proc:save`).
+    pub load_threads: Option<u32>,
+    /// GPU processing threads (second field of `-j load:proc:save`).
+    pub proc_threads: Option<u32>,
+    /// Image encode threads (third field of `-j load:proc:save`).
+    pub save_threads: Option<u32>,
+    /// Enables test-time augmentation (`-x`): ~8x slower, slightly cleaner output.
+    pub tta: bool,
+    /// Intermediate image format written by the upscaler (`-f`); defaults to PNG.
+    pub output_format: Option<UpscaledFormat>,
+}
+
+impl UpscalerOptions {
+    /// Returns the format the upscaler writes, falling back to the binaries' PNG default.
+    pub fn format(&self) -> UpscaledFormat {
+        self.output_format.unwrap_or(UpscaledFormat::Png)
+    }
+
+    /// Validates the options against what a backend declares it supports.
+    pub fn validate(&self, caps: &BackendCapabilities) -> Result<(), PixyError> {
+        if let Some(t) = self.tile_size {
+            if !caps.tile_size {
+                return Err(PixyError::InvalidArgument(
+                    "tile size is not supported by this upscaler",
+                ));
             }
-            UpscalerKind::RealCugan => {
-                let mut c = Command::new(&self.path);
-                c.args([
-                    "-i",
-                    input_pattern.to_string_lossy().as_ref(),
-                    "-o",
-                    output_pattern.to_string_lossy().as_ref(),
-                    "-g",
-                ])
-                .arg(gpu.to_string());
-                if let Some(t) = tile_size {
-                    c.args(["-t", &t.to_string()]);
-                }
-                if let Some(level) = model.denoise_level {
-                    c.args(["-n", &level.to_string()]);
-                }
-                c
+            if t != 0 && t < 32 {
+                return Err(PixyError::InvalidArgument(
+                    "tile size must be 0 (auto) or at least 32",
+                ));
             }
-            UpscalerKind::Waifu2x => {
-                let mut c = Command::new(&self.path);
-                c.args([
-                    "-i",
-                    input_pattern.to_string_lossy().as_ref(),
-                    "-o",
-                    output_pattern.to_string_lossy().as_ref(),
-                    "-g",
-                ])
-                .arg(gpu.to_string());
-                if let Some(t) = tile_size {
-                    c.args(["-t", &t.to_string()]);
-                }
-                if let Some(level) = model.denoise_level {
-                    c.args(["-n", &level.to_string()]);
-                }
-                c
+        }
+        let threads = [self.load_threads, self.proc_threads, self.save_threads];
+        if threads.iter().flatten().any(|&n| n == 0) {
+            return Err(PixyError::InvalidArgument(
+                "load/proc/save thread counts must be at least 1",
+            ));
+        }
+        if threads.iter().any(Option::is_some) && !caps.thread_tuning {
+            return Err(PixyError::InvalidArgument(
+                "thread tuning is not supported by this upscaler",
+            ));
+        }
+        if self.tta && !caps.tta {
+            return Err(PixyError::InvalidArgument(
+                "TTA mode is not supported by this upscaler",
+            ));
+        }
+        if let Some(format) = self.output_format {
+            if !caps.output_formats.contains(&format) {
+                return Err(PixyError::InvalidArgument(
+                    "output format is not supported by this upscaler",
+                ));
             }
-        };
-
-        let status = cmd.status()?;
-        if !status.success() {
-            return Err(PixyError::ProcessFailed {
-                cmd: format!("{:?}", cmd),
-                code: status.code(),
-                stderr: String::new(),
-            });
         }
         Ok(())
     }
 }
 
-fn sequence_dir_arg(path: &Path) -> Cow<'_, str> {
+/// Features a backend supports, used to validate `UpscalerOptions` before running.
+#[derive(Debug, Clone)]
+pub struct BackendCapabilities {
+    pub tile_size: bool,
+    pub thread_tuning: bool,
+    pub tta: bool,
+    pub gpu_selection: bool,
+    /// Keeps 16-bit PNG input at 16 bits instead of truncating to 8.
+    pub high_bit_depth: bool,
+    pub output_formats: Vec<UpscaledFormat>,
+}
+
+/// Everything a backend needs for one run over an image sequence.
+#[derive(Debug, Clone, Copy)]
+pub struct UpscaleRequest<'a> {
+    pub input_pattern: &'a Path,
+    pub output_pattern: &'a Path,
+    pub gpu: usize,
+    pub options: &'a UpscalerOptions,
+    pub model: &'a ModelSpec,
+}
+
+/// An upscaler that turns an input image sequence into an upscaled one.
+/// Why: Lets downstream crates plug in their own tools without forking `pixy_core`;
+/// the built-in ncnn binaries are implemented on top of the same trait.
+pub trait UpscalerBackend: fmt::Debug + Send + Sync {
+    /// Stable identifier, e.g. `realesrgan`; matches `ModelKind::id` for built-ins.
+    fn id(&self) -> &str;
+
+    /// Options this backend understands.
+    fn capabilities(&self) -> BackendCapabilities;
+
+    /// Scale factors the backend can produce for the given model.
+    fn supported_scales(&self, model: &ModelSpec) -> Vec<u32>;
+
+    /// Denoise levels accepted for the given model; empty when denoising is not configurable.
+    fn supported_denoise(&self, model: &ModelSpec) -> Vec<u8>;
+
+    /// Locates the executable that `build_args` targets.
+    fn find_binary(&self) -> Result<PathBuf, PixyError>;
+
+    /// Builds the command line for one run.
+    fn build_args(&self, request: &UpscaleRequest<'_>) -> Result<Vec<OsString>, PixyError>;
+
+    /// Parses a line of tool output into a structured event.
+    fn parse_progress(&self, line: &str) -> UpscalerEvent {
+        log::parse_line(line)
+    }
+
+    /// Validates the request, then runs the binary and streams its output through
+    /// `parse_progress` into `on_event`.
+    fn run(
+        &self,
+        request: &UpscaleRequest<'_>,
+        on_event: &mut dyn FnMut(UpscalerEvent),
+    ) -> Result<(), PixyError> {
+        validate_request(self, request)?;
+        let mut cmd = Command::new(self.find_binary()?);
+        cmd.args(self.build_args(request)?);
+        log::run_streaming(&mut cmd, &|line| self.parse_progress(line), on_event)
+    }
+}
+
+/// Checks options, scale and denoise level against what `backend` declares.
+pub fn validate_request<B: UpscalerBackend + ?Sized>(
+    backend: &B,
+    request: &UpscaleRequest<'_>,
+) -> Result<(), PixyError> {
+    request.options.validate(&backend.capabilities())?;
+    if !backend
+        .supported_scales(request.model)
+        .contains(&request.model.scale)
+    {
+        return Err(PixyError::InvalidArgument(
+            "model scale is not supported by this upscaler",
+        ));
+    }
+    if let Some(level) = request.model.denoise_level {
+        if !backend.supported_denoise(request.model).contains(&level) {
+            return Err(PixyError::InvalidArgument(
+                "denoise level is not supported by this upscaler",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the built-in backend serving a model kind, or `None` for custom kinds.
+pub fn backend_for(kind: &ModelKind) -> Option<Arc<dyn UpscalerBackend>> {
+    match kind {
+        ModelKind::RealEsrgan => Some(Arc::new(RealEsrgan)),
+        ModelKind::RealCugan => Some(Arc::new(RealCugan)),
+        ModelKind::Waifu2x => Some(Arc::new(Waifu2x)),
+        ModelKind::Custom(_) => None,
+    }
+}
+
+pub(crate) fn sequence_dir_arg(path: &Path) -> Cow<'_, str> {
     let as_str = path.to_string_lossy();
     if as_str.contains('%') {
         if let Some(parent) = path.parent() {
@@ -114,15 +208,3 @@ fn sequence_dir_arg(path: &Path) -> Cow<'_, str> {
     }
     as_str
 }
-
-/// Finds the executable path for a given upscaler kind using platform-aware resolution.
-/// Why: Bundled resources and user PATHs vary across OSes and install methods.
-pub fn find_upscaler_binary(kind: UpscalerKind) -> Result<UpscalerBinary, PixyError> {
-    let name = match kind {
-        UpscalerKind::RealEsrgan => "realesrgan-ncnn-vulkan",
-        UpscalerKind::RealCugan => "realcugan-ncnn-vulkan",
-        UpscalerKind::Waifu2x => "waifu2x-ncnn-vulkan",
-    };
-    let path = resolve_tool(name)?;
-    Ok(UpscalerBinary { kind, path })
-}
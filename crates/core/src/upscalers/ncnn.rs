@@ -0,0 +1,221 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::error::PixyError;
+use crate::models::ModelSpec;
+use crate::paths::resolve_tool;
+
+use super::{
+    sequence_dir_arg, BackendCapabilities, UpscaleRequest, UpscaledFormat, UpscalerBackend,
+    UpscalerOptions,
+};
+
+/// Capabilities shared by the nihui/xinntao ncnn-vulkan tools.
+fn ncnn_capabilities() -> BackendCapabilities {
+    BackendCapabilities {
+        tile_size: true,
+        thread_tuning: true,
+        tta: true,
+        gpu_selection: true,
+        // The tools decode through stb_image/WIC into 8-bit buffers.
+        high_bit_depth: false,
+        output_formats: vec![
+            UpscaledFormat::Png,
+            UpscaledFormat::Jpg,
+            UpscaledFormat::Webp,
+        ],
+    }
+}
+
+/// Builds the `-i/-o/-g` arguments every ncnn tool takes. The tools expect
+/// directories, so printf-style sequence patterns are reduced to their parent.
+fn ncnn_io_args(request: &UpscaleRequest<'_>) -> Vec<OsString> {
+    vec![
+        "-i".into(),
+        sequence_dir_arg(request.input_pattern).into_owned().into(),
+        "-o".into(),
+        sequence_dir_arg(request.output_pattern).into_owned().into(),
+        "-g".into(),
+        request.gpu.to_string().into(),
+    ]
+}
+
+/// Translates the options into the `-t`, `-j`, `-x` and `-f` flags shared by the ncnn tools,
+/// plus `-v` so each finished image is reported for progress.
+fn ncnn_tuning_args(options: &UpscalerOptions) -> Vec<OsString> {
+    let mut args: Vec<OsString> = Vec::new();
+    if let Some(t) = options.tile_size {
+        args.push("-t".into());
+        args.push(t.to_string().into());
+    }
+    if options.load_threads.is_some()
+        || options.proc_threads.is_some()
+        || options.save_threads.is_some()
+    {
+        // ncnn defaults are 1:2:2; only override the fields the user set.
+        args.push("-j".into());
+        args.push(
+            format!(
+                "{}:{}:{}",
+                options.load_threads.unwrap_or(1),
+                options.proc_threads.unwrap_or(2),
+                options.save_threads.unwrap_or(2)
+            )
+            .into(),
+        );
+    }
+    if options.tta {
+        args.push("-x".into());
+    }
+    if let Some(format) = options.output_format {
+        args.push("-f".into());
+        args.push(format.extension().into());
+    }
+    args.push("-v".into());
+    args
+}
+
+/// Adds `-m <dir>` when the model points at a custom model directory.
+fn ncnn_model_dir_args(model: &ModelSpec, args: &mut Vec<OsString>) {
+    if let Some(path) = &model.path {
+        args.push("-m".into());
+        args.push(path.into());
+    }
+}
+
+/// Adds `-n <level>` when one is set; otherwise the tool's own default applies (0 for
+/// waifu2x, -1 for Real-CUGAN).
+fn ncnn_denoise_args(model: &ModelSpec, args: &mut Vec<OsString>) {
+    if let Some(level) = model.denoise_level {
+        args.push("-n".into());
+        args.push(level.to_string().into());
+    }
+}
+
+/// Real-ESRGAN via `realesrgan-ncnn-vulkan`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealEsrgan;
+
+/// The animevideov3 family ships one weight file per scale; the binary appends `-x<scale>`.
+const ANIMEVIDEO_V3: &str = "realesr-animevideov3";
+
+impl UpscalerBackend for RealEsrgan {
+    fn id(&self) -> &str {
+        "realesrgan"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        ncnn_capabilities()
+    }
+
+    fn supported_scales(&self, model: &ModelSpec) -> Vec<u32> {
+        if model.name.starts_with(ANIMEVIDEO_V3) {
+            vec![2, 3, 4]
+        } else {
+            // Other Real-ESRGAN weights have their scale baked in.
+            vec![model.scale]
+        }
+    }
+
+    fn supported_denoise(&self, _model: &ModelSpec) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn find_binary(&self) -> Result<PathBuf, PixyError> {
+        resolve_tool("realesrgan-ncnn-vulkan")
+    }
+
+    fn build_args(&self, request: &UpscaleRequest<'_>) -> Result<Vec<OsString>, PixyError> {
+        let model = request.model;
+        let name = if model.name.starts_with(ANIMEVIDEO_V3) {
+            ANIMEVIDEO_V3
+        } else {
+            model.name.as_str()
+        };
+        let mut args = ncnn_io_args(request);
+        args.extend([
+            "-n".into(),
+            name.into(),
+            "-s".into(),
+            model.scale.to_string().into(),
+        ]);
+        ncnn_model_dir_args(model, &mut args);
+        args.extend(ncnn_tuning_args(request.options));
+        Ok(args)
+    }
+}
+
+/// Real-CUGAN via `realcugan-ncnn-vulkan`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealCugan;
+
+impl UpscalerBackend for RealCugan {
+    fn id(&self) -> &str {
+        "realcugan"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        ncnn_capabilities()
+    }
+
+    fn supported_scales(&self, _model: &ModelSpec) -> Vec<u32> {
+        vec![2, 3, 4]
+    }
+
+    fn supported_denoise(&self, model: &ModelSpec) -> Vec<u8> {
+        // The SE weights only ship conservative/no-denoise/3x variants beyond 2x.
+        if model.scale == 2 {
+            vec![0, 1, 2, 3]
+        } else {
+            vec![0, 3]
+        }
+    }
+
+    fn find_binary(&self) -> Result<PathBuf, PixyError> {
+        resolve_tool("realcugan-ncnn-vulkan")
+    }
+
+    fn build_args(&self, request: &UpscaleRequest<'_>) -> Result<Vec<OsString>, PixyError> {
+        let mut args = ncnn_io_args(request);
+        args.extend(["-s".into(), request.model.scale.to_string().into()]);
+        ncnn_denoise_args(request.model, &mut args);
+        ncnn_model_dir_args(request.model, &mut args);
+        args.extend(ncnn_tuning_args(request.options));
+        Ok(args)
+    }
+}
+
+/// Waifu2x via `waifu2x-ncnn-vulkan`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Waifu2x;
+
+impl UpscalerBackend for Waifu2x {
+    fn id(&self) -> &str {
+        "waifu2x"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        ncnn_capabilities()
+    }
+
+    fn supported_scales(&self, _model: &ModelSpec) -> Vec<u32> {
+        vec![1, 2, 4, 8, 16, 32]
+    }
+
+    fn supported_denoise(&self, _model: &ModelSpec) -> Vec<u8> {
+        vec![0, 1, 2, 3]
+    }
+
+    fn find_binary(&self) -> Result<PathBuf, PixyError> {
+        resolve_tool("waifu2x-ncnn-vulkan")
+    }
+
+    fn build_args(&self, request: &UpscaleRequest<'_>) -> Result<Vec<OsString>, PixyError> {
+        let mut args = ncnn_io_args(request);
+        args.extend(["-s".into(), request.model.scale.to_string().into()]);
+        ncnn_denoise_args(request.model, &mut args);
+        ncnn_model_dir_args(request.model, &mut args);
+        args.extend(ncnn_tuning_args(request.options));
+        Ok(args)
+    }
+}
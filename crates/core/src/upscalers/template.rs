@@ -0,0 +1,277 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::PixyError;
+use crate::models::ModelSpec;
+use crate::paths::resolve_tool;
+
+use super::{
+    sequence_dir_arg, BackendCapabilities, UpscaleRequest, UpscaledFormat, UpscalerBackend,
+    UpscalerEvent,
+};
+
+/// Placeholders understood in `args` entries.
+const PLACEHOLDERS: &[&str] = &[
+    "input",
+    "output",
+    "input_pattern",
+    "output_pattern",
+    "gpu",
+    "tile",
+    "scale",
+    "model",
+    "model_path",
+    "denoise",
+    "format",
+];
+
+/// An `args` entry: a plain argument, or a group that is dropped entirely when any
+/// placeholder in it has no value (e.g. `["-t", "{tile}"]` without a tile size).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum TemplateArg {
+    Single(String),
+    Group(Vec<String>),
+}
+
+/// Backend description loaded from TOML.
+///
+/// ```toml
+/// id = "anime4k"
+/// binary = "anime4k-cli"
+/// args = ["-i", "{input}", "-o", "{output}", "-z", "{scale}", ["-t", "{tile}"]]
+/// progress_regex = '(?P<percent>\d+(\.\d+)?)%'
+/// scales = [2, 4]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateSpec {
+    /// Backend identifier; models select it with `ModelKind::Custom(id)`.
+    pub id: String,
+    /// Executable name resolved through `paths::resolve_tool`.
+    pub binary: String,
+    pub args: Vec<TemplateArg>,
+    /// Regex with a `percent` group matching progress lines.
+    #[serde(default)]
+    pub progress_regex: Option<String>,
+    /// Regex matching "image finished" lines; optional `input`/`output` groups.
+    #[serde(default)]
+    pub done_regex: Option<String>,
+    #[serde(default = "default_scales")]
+    pub scales: Vec<u32>,
+    #[serde(default)]
+    pub denoise: Vec<u8>,
+    /// Arguments appended when TTA is requested; TTA is unsupported when empty.
+    #[serde(default)]
+    pub tta_args: Vec<String>,
+    /// Formats the `{format}` placeholder may take; PNG only when empty.
+    #[serde(default)]
+    pub output_formats: Vec<String>,
+    /// Whether the tool keeps 16-bit PNG input at 16 bits.
+    #[serde(default)]
+    pub high_bit_depth: bool,
+}
+
+fn default_scales() -> Vec<u32> {
+    vec![2]
+}
+
+/// Backend driven by a `TemplateSpec`.
+/// Why: ncnn-like tools can be added from a config file without recompiling.
+#[derive(Debug, Clone)]
+pub struct TemplateBackend {
+    spec: TemplateSpec,
+    progress: Option<Regex>,
+    done: Option<Regex>,
+    output_formats: Vec<UpscaledFormat>,
+}
+
+impl TemplateBackend {
+    /// Builds a backend from a parsed spec, validating placeholders and regexes.
+    pub fn new(spec: TemplateSpec) -> Result<Self, PixyError> {
+        for arg in spec.args.iter().flat_map(TemplateArg::parts) {
+            for name in placeholders(arg) {
+                if !PLACEHOLDERS.contains(&name) {
+                    return Err(PixyError::InvalidArgument(
+                        "unknown placeholder in backend template",
+                    ));
+                }
+            }
+        }
+        let progress = spec.progress_regex.as_deref().map(Regex::new).transpose()?;
+        if progress
+            .as_ref()
+            .is_some_and(|re| !re.capture_names().any(|n| n == Some("percent")))
+        {
+            return Err(PixyError::InvalidArgument(
+                "progress_regex needs a `percent` capture group",
+            ));
+        }
+        let done = spec.done_regex.as_deref().map(Regex::new).transpose()?;
+        let output_formats = parse_formats(&spec.output_formats)?;
+        Ok(Self {
+            spec,
+            progress,
+            done,
+            output_formats,
+        })
+    }
+
+    /// Parses a TOML template.
+    pub fn from_toml_str(source: &str) -> Result<Self, PixyError> {
+        Self::new(toml::from_str(source)?)
+    }
+
+    /// Loads a TOML template from disk.
+    pub fn from_file(path: &Path) -> Result<Self, PixyError> {
+        Self::from_toml_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn spec(&self) -> &TemplateSpec {
+        &self.spec
+    }
+}
+
+impl TemplateArg {
+    fn parts(&self) -> &[String] {
+        match self {
+            TemplateArg::Single(s) => std::slice::from_ref(s),
+            TemplateArg::Group(v) => v,
+        }
+    }
+}
+
+impl UpscalerBackend for TemplateBackend {
+    fn id(&self) -> &str {
+        &self.spec.id
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        let uses = |name: &str| {
+            self.spec
+                .args
+                .iter()
+                .flat_map(TemplateArg::parts)
+                .any(|a| placeholders(a).any(|p| p == name))
+        };
+        BackendCapabilities {
+            tile_size: uses("tile"),
+            thread_tuning: false,
+            tta: !self.spec.tta_args.is_empty(),
+            gpu_selection: uses("gpu"),
+            high_bit_depth: self.spec.high_bit_depth,
+            output_formats: self.output_formats.clone(),
+        }
+    }
+
+    fn supported_scales(&self, _model: &ModelSpec) -> Vec<u32> {
+        self.spec.scales.clone()
+    }
+
+    fn supported_denoise(&self, _model: &ModelSpec) -> Vec<u8> {
+        self.spec.denoise.clone()
+    }
+
+    fn find_binary(&self) -> Result<PathBuf, PixyError> {
+        resolve_tool(&self.spec.binary)
+    }
+
+    fn build_args(&self, request: &UpscaleRequest<'_>) -> Result<Vec<OsString>, PixyError> {
+        let value = |name: &str| -> Option<String> {
+            match name {
+                "input" => Some(sequence_dir_arg(request.input_pattern).into_owned()),
+                "output" => Some(sequence_dir_arg(request.output_pattern).into_owned()),
+                "input_pattern" => Some(request.input_pattern.to_string_lossy().into_owned()),
+                "output_pattern" => Some(request.output_pattern.to_string_lossy().into_owned()),
+                "gpu" => Some(request.gpu.to_string()),
+                "tile" => request.options.tile_size.map(|t| t.to_string()),
+                "scale" => Some(request.model.scale.to_string()),
+                "model" => Some(request.model.name.clone()),
+                "model_path" => request.model.path.clone(),
+                "denoise" => request.model.denoise_level.map(|l| l.to_string()),
+                "format" => Some(request.options.format().extension().to_string()),
+                _ => None,
+            }
+        };
+
+        let mut args = Vec::new();
+        for arg in &self.spec.args {
+            let expanded: Option<Vec<String>> = arg
+                .parts()
+                .iter()
+                .map(|part| expand(part, &value))
+                .collect();
+            match (arg, expanded) {
+                (_, Some(parts)) => args.extend(parts.into_iter().map(OsString::from)),
+                (TemplateArg::Group(_), None) => {}
+                (TemplateArg::Single(_), None) => {
+                    return Err(PixyError::InvalidArgument(
+                        "backend template argument has no value; wrap it in an optional group",
+                    ))
+                }
+            }
+        }
+        if request.options.tta {
+            args.extend(self.spec.tta_args.iter().map(OsString::from));
+        }
+        Ok(args)
+    }
+
+    fn parse_progress(&self, line: &str) -> UpscalerEvent {
+        if let Some(caps) = self.progress.as_ref().and_then(|re| re.captures(line)) {
+            if let Some(percent) = caps.name("percent").and_then(|m| m.as_str().parse().ok()) {
+                return UpscalerEvent::Progress { percent };
+            }
+        }
+        if let Some(caps) = self.done.as_ref().and_then(|re| re.captures(line)) {
+            let path = |name| caps.name(name).map_or("", |m| m.as_str()).into();
+            return UpscalerEvent::FileDone {
+                input: path("input"),
+                output: path("output"),
+            };
+        }
+        UpscalerEvent::Log(line.to_string())
+    }
+}
+
+/// Iterates over `{name}` placeholders in a template string.
+fn placeholders(s: &str) -> impl Iterator<Item = &str> {
+    s.split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+}
+
+/// Substitutes every placeholder, returning `None` when one has no value.
+fn expand(template: &str, value: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(&value(&rest[start + 1..start + len])?);
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+fn parse_formats(names: &[String]) -> Result<Vec<UpscaledFormat>, PixyError> {
+    if names.is_empty() {
+        return Ok(vec![UpscaledFormat::Png]);
+    }
+    names
+        .iter()
+        .map(|name| match name.to_ascii_lowercase().as_str() {
+            "png" => Ok(UpscaledFormat::Png),
+            "jpg" | "jpeg" => Ok(UpscaledFormat::Jpg),
+            "webp" => Ok(UpscaledFormat::Webp),
+            _ => Err(PixyError::InvalidArgument(
+                "backend template output_formats must be png, jpg or webp",
+            )),
+        })
+        .collect()
+}
@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use image::{GrayImage, Luma};
+use pixy_core::dedup::{find_duplicates, DedupOptions};
+
+/// Writes `frames` as a numbered sequence into a fresh scratch directory.
+fn sequence(name: &str, frames: &[GrayImage]) -> Vec<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("pixy-uppy-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let path = dir.join(format!("{:08}.png", i + 1));
+            frame.save(&path).unwrap();
+            path
+        })
+        .collect()
+}
+
+/// 60x36 gradient: neither side is a multiple of the 8-pixel block.
+fn gradient() -> GrayImage {
+    GrayImage::from_fn(60, 36, |x, y| Luma([(x * 3 + y * 2) as u8]))
+}
+
+#[test]
+fn maps_repeated_frames_to_the_last_unique_one() {
+    let mut grain = gradient();
+    for (x, y, p) in grain.enumerate_pixels_mut() {
+        p[0] = p[0].saturating_add(((x + y) % 2) as u8);
+    }
+    let other = GrayImage::from_fn(60, 36, |x, _| Luma([255 - (x * 4) as u8]));
+    let frames = sequence("dedup-map", &[gradient(), grain, other.clone(), other]);
+
+    let map = find_duplicates(&frames, &DedupOptions::default()).unwrap();
+    assert_eq!(map.sources, [0, 0, 2, 2]);
+    assert_eq!(map.unique_count(), 2);
+}
+
+#[test]
+fn sees_changes_in_partial_edge_blocks() {
+    let mut bottom = gradient();
+    let mut right = gradient();
+    for x in 0..8 {
+        for y in 34..36 {
+            bottom.put_pixel(x, y, Luma([255]));
+        }
+    }
+    for x in 56..60 {
+        for y in 0..4 {
+            right.put_pixel(x, y, Luma([255]));
+        }
+    }
+    let frames = sequence("dedup-edges", &[gradient(), bottom, right]);
+
+    let map = find_duplicates(&frames, &DedupOptions::default()).unwrap();
+    assert_eq!(map.sources, [0, 1, 2]);
+}
+
+#[test]
+fn keeps_frames_with_one_strongly_changed_block() {
+    let mut spot = gradient();
+    for x in 16..24 {
+        for y in 8..16 {
+            spot.put_pixel(x, y, Luma([255]));
+        }
+    }
+    let frames = sequence("dedup-spot", &[gradient(), spot]);
+
+    let map = find_duplicates(&frames, &DedupOptions::default()).unwrap();
+    assert_eq!(map.sources, [0, 1]);
+}
@@ -0,0 +1,241 @@
+use std::path::Path;
+
+use pixy_core::container::Container;
+use pixy_core::encoder::{EncoderKind, EncoderOptions, GopOptions, RateControl};
+use pixy_core::PixyError;
+
+fn options(encoder: EncoderKind, rate_control: RateControl) -> EncoderOptions {
+    EncoderOptions {
+        rate_control: Some(rate_control),
+        ..EncoderOptions::new(encoder)
+    }
+}
+
+fn args(encoder: EncoderKind, rate_control: RateControl) -> String {
+    options(encoder, rate_control).to_ffmpeg_args().join(" ")
+}
+
+#[test]
+fn maps_constant_quality_to_each_encoders_own_option() {
+    let cq = RateControl::ConstantQuality(23);
+    assert_eq!(args(EncoderKind::Libx265, cq), "-c:v libx265 -crf 23");
+    assert_eq!(
+        args(EncoderKind::HevcNvenc, cq),
+        "-c:v hevc_nvenc -rc vbr -cq 23 -b:v 0"
+    );
+    assert_eq!(
+        args(EncoderKind::H264Amf, cq),
+        "-c:v h264_amf -rc cqp -qp_i 23 -qp_p 23 -qp_b 23"
+    );
+    assert_eq!(
+        args(EncoderKind::HevcQsv, cq),
+        "-c:v hevc_qsv -global_quality 23"
+    );
+    assert_eq!(
+        args(EncoderKind::H264Vaapi, cq),
+        "-c:v h264_vaapi -rc_mode CQP -qp 23"
+    );
+}
+
+#[test]
+fn maps_bitrate_modes() {
+    assert_eq!(
+        args(EncoderKind::HevcNvenc, RateControl::Cbr { bitrate: 8000 }),
+        "-c:v hevc_nvenc -rc cbr -b:v 8000k -minrate 8000k -maxrate 8000k -bufsize 16000k"
+    );
+    assert_eq!(
+        args(
+            EncoderKind::Libx264,
+            RateControl::Vbr {
+                bitrate: 8000,
+                max_bitrate: 12000
+            }
+        ),
+        "-c:v libx264 -b:v 8000k -maxrate 12000k -bufsize 24000k"
+    );
+}
+
+#[test]
+fn rejects_out_of_range_rate_control() {
+    let invalid = [
+        options(EncoderKind::Libx264, RateControl::ConstantQuality(52)),
+        options(EncoderKind::HevcQsv, RateControl::ConstantQuality(0)),
+        options(EncoderKind::Av1Nvenc, RateControl::ConstantQuality(64)),
+        options(EncoderKind::ProresKs, RateControl::TargetBitrate(50_000)),
+        options(
+            EncoderKind::Libx264,
+            RateControl::Vbr {
+                bitrate: 8000,
+                max_bitrate: 4000,
+            },
+        ),
+    ];
+    for opts in invalid {
+        assert!(matches!(
+            opts.validate(),
+            Err(PixyError::InvalidArgument(_))
+        ));
+    }
+    for encoder in [EncoderKind::LibvpxVp9, EncoderKind::Av1Nvenc] {
+        assert!(options(encoder, RateControl::ConstantQuality(63))
+            .validate()
+            .is_ok());
+    }
+}
+
+#[test]
+fn adapts_rate_control_when_switching_to_an_alpha_encoder() {
+    let alpha = |encoder, rate_control| {
+        options(encoder, rate_control)
+            .with_alpha(Some(Container::Mov))
+            .unwrap()
+    };
+    let prores = alpha(EncoderKind::Libx265, RateControl::ConstantQuality(40));
+    assert_eq!(prores.encoder, EncoderKind::ProresKs);
+    assert_eq!(prores.rate_control, Some(RateControl::ConstantQuality(32)));
+    assert!(prores.validate().is_ok());
+    for rate_control in [
+        RateControl::TargetBitrate(8000),
+        RateControl::TargetSize { bytes: 1 << 20 },
+        RateControl::Lossless,
+    ] {
+        assert_eq!(alpha(EncoderKind::Libx264, rate_control).rate_control, None);
+    }
+
+    let vp9 = options(EncoderKind::Av1Amf, RateControl::ConstantQuality(200))
+        .with_alpha(Some(Container::Mkv))
+        .unwrap();
+    assert_eq!(vp9.rate_control, Some(RateControl::ConstantQuality(63)));
+    let vp9 = options(EncoderKind::Libx264, RateControl::Lossless)
+        .with_alpha(Some(Container::WebM))
+        .unwrap();
+    assert_eq!(vp9.rate_control, Some(RateControl::Lossless));
+}
+
+#[test]
+fn maps_presets_and_lossless_per_encoder() {
+    let mut svt = options(EncoderKind::Libsvtav1, RateControl::ConstantQuality(30));
+    svt.preset = Some("6".into());
+    assert_eq!(
+        svt.to_ffmpeg_args().join(" "),
+        "-c:v libsvtav1 -preset 6 -crf 30"
+    );
+    let mut aom = options(EncoderKind::LibaomAv1, RateControl::ConstantQuality(30));
+    aom.preset = Some("4".into());
+    assert_eq!(
+        aom.to_ffmpeg_args().join(" "),
+        "-c:v libaom-av1 -cpu-used 4 -crf 30 -b:v 0"
+    );
+    let mut prores = options(EncoderKind::ProresKs, RateControl::ConstantQuality(9));
+    prores.preset = Some("lt".into());
+    assert_eq!(
+        prores.to_ffmpeg_args().join(" "),
+        "-c:v prores_ks -qscale:v 9 -profile:v lt"
+    );
+    assert_eq!(
+        args(EncoderKind::Libx265, RateControl::Lossless),
+        "-c:v libx265 -x265-params lossless=1"
+    );
+    assert_eq!(
+        args(EncoderKind::Ffv1, RateControl::Lossless),
+        "-c:v ffv1 -level 3 -g 1 -slices 16 -slicecrc 1"
+    );
+}
+
+#[test]
+fn rejects_presets_and_modes_an_encoder_lacks() {
+    let mut svt = options(EncoderKind::Libsvtav1, RateControl::ConstantQuality(30));
+    svt.preset = Some("medium".into());
+    assert!(svt.validate().is_err());
+    assert!(options(EncoderKind::Ffv1, RateControl::ConstantQuality(0))
+        .validate()
+        .is_err());
+    assert!(options(EncoderKind::HevcNvenc, RateControl::Lossless)
+        .validate()
+        .is_err());
+    assert!(
+        options(EncoderKind::Av1Vaapi, RateControl::ConstantQuality(120))
+            .validate()
+            .is_ok()
+    );
+}
+
+#[test]
+fn merges_gop_and_codec_params_into_one_params_option() {
+    let mut x265 = options(EncoderKind::Libx265, RateControl::Lossless);
+    x265.gop = GopOptions {
+        keyint: Some(240),
+        min_keyint: Some(24),
+        bframes: Some(4),
+        lookahead: Some(40),
+    };
+    x265.codec_params = [("aq-mode", "3"), ("psy-rd", "2.0")]
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .into();
+    x265.extra_args = vec!["-metadata:s:v".into(), "title=master".into()];
+    x265.validate().unwrap();
+    assert_eq!(
+        x265.to_ffmpeg_args().join(" "),
+        "-c:v libx265 -g 240 -keyint_min 24 -bf 4 \
+         -x265-params aq-mode=3:lossless=1:psy-rd=2.0:rc-lookahead=40 -metadata:s:v title=master"
+    );
+}
+
+#[test]
+fn rejects_extra_args_that_touch_the_pipeline() {
+    for args in [
+        vec!["-map", "0:a"],
+        vec!["-c:v", "libx264"],
+        vec!["-filter:v", "scale=640:-1"],
+        vec!["-f", "mp4"],
+        vec!["-af", "volume=2"],
+        vec!["-filter_complex_script", "graph.txt"],
+        vec!["-x264-params", "keyint=1"],
+        vec!["-svtav1-params", "tune=0"],
+        vec!["-pass", "1"],
+        vec!["-passlogfile", "/tmp/stats"],
+    ] {
+        let mut opts = options(EncoderKind::Libx264, RateControl::ConstantQuality(18));
+        opts.extra_args = args.into_iter().map(String::from).collect();
+        assert!(opts.validate().is_err(), "{:?}", opts.extra_args);
+    }
+    let mut opts = options(EncoderKind::LibvpxVp9, RateControl::ConstantQuality(30));
+    opts.extra_args = vec![
+        "-row-mt".into(),
+        "1".into(),
+        "-arnr-strength".into(),
+        "-1".into(),
+        "-map_metadata".into(),
+        "-1".into(),
+        "-map_chapters".into(),
+        "0".into(),
+    ];
+    opts.validate().unwrap();
+    opts.gop.bframes = Some(2);
+    assert!(opts.validate().is_err());
+}
+
+#[test]
+fn passes_two_pass_stats_per_encoder() {
+    let stats = Path::new("/work/passlog");
+    let target = |encoder| options(encoder, RateControl::TargetBitrate(4000));
+    let x264 = target(EncoderKind::Libx264);
+    assert!(x264.is_two_pass());
+    let args = x264.pass_ffmpeg_args(1, stats);
+    assert!(args
+        .windows(4)
+        .any(|w| w == ["-pass", "1", "-passlogfile", "/work/passlog"]));
+
+    let x265 = target(EncoderKind::Libx265).pass_ffmpeg_args(2, stats);
+    assert!(x265
+        .windows(2)
+        .any(|w| w == ["-x265-params", "pass=2:stats=/work/passlog"]));
+    assert!(!x265.contains(&"-pass".to_string()));
+
+    let nvenc = target(EncoderKind::HevcNvenc);
+    assert!(!nvenc.is_two_pass());
+    assert!(nvenc
+        .to_ffmpeg_args()
+        .windows(2)
+        .any(|w| w == ["-multipass", "fullres"]));
+}
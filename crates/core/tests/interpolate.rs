@@ -0,0 +1,64 @@
+use pixy_core::interpolate::{InterpolationOptions, InterpolationTarget};
+use pixy_core::probe::FrameRate;
+use pixy_core::PixyError;
+
+#[test]
+fn parses_frame_rates() {
+    assert_eq!(
+        FrameRate::parse("24000/1001"),
+        Some(FrameRate::new(24000, 1001))
+    );
+    assert_eq!(FrameRate::parse(" 30 / 1 "), Some(FrameRate::new(30, 1)));
+    assert_eq!(FrameRate::parse("60"), Some(FrameRate::new(60000, 1000)));
+    assert_eq!(FrameRate::parse("29.97"), Some(FrameRate::new(29970, 1000)));
+    for bad in ["0/0", "24/0", "0", "-25", "NaN", "fast", ""] {
+        assert_eq!(FrameRate::parse(bad), None, "{bad}");
+    }
+}
+
+#[test]
+fn multiplies_frame_rates_without_overflowing() {
+    let ntsc = FrameRate::new(24000, 1001);
+    assert_eq!(ntsc.times(2).unwrap(), FrameRate::new(48000, 1001));
+    assert!(matches!(
+        ntsc.times(u32::MAX),
+        Err(PixyError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn counts_output_frames_for_the_target_rate() {
+    let source = FrameRate::new(24, 1);
+    let doubled = InterpolationOptions::default();
+    assert_eq!(doubled.output_frames(3, source).unwrap(), 6);
+    let sixty = InterpolationOptions {
+        target: InterpolationTarget::Fps(FrameRate::new(60, 1)),
+        ..InterpolationOptions::default()
+    };
+    assert_eq!(sixty.output_rate(source).unwrap(), FrameRate::new(60, 1));
+    assert_eq!(sixty.output_frames(48, source).unwrap(), 120);
+}
+
+#[test]
+fn validates_multiplier_and_scene_threshold() {
+    assert!(InterpolationOptions::default().validate().is_ok());
+    for invalid in [
+        InterpolationOptions {
+            target: InterpolationTarget::Multiplier(0),
+            ..InterpolationOptions::default()
+        },
+        InterpolationOptions {
+            scene_threshold: Some(1.5),
+            ..InterpolationOptions::default()
+        },
+        InterpolationOptions {
+            scene_threshold: Some(-0.1),
+            ..InterpolationOptions::default()
+        },
+    ] {
+        assert!(matches!(
+            invalid.validate(),
+            Err(PixyError::InvalidArgument(_))
+        ));
+    }
+}
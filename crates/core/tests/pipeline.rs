@@ -0,0 +1,941 @@
+#![cfg(unix)]
+
+use std::path::Path;
+use std::sync::Arc;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgb, RgbImage, Rgba, RgbaImage};
+use pixy_core::alpha::AlphaMode;
+use pixy_core::animation::{encode_animation, AnimationFormat, LoopCount};
+use pixy_core::audio::{AudioCodec, AudioOptions, AudioSelection, Loudness};
+use pixy_core::container::Container;
+use pixy_core::dedup::DedupOptions;
+use pixy_core::devices::VulkanDevice;
+use pixy_core::encoder::{
+    auto_select_encoder, available_encoders, EncoderKind, EncoderOptions, RateControl,
+};
+use pixy_core::frames::FrameExtractOptions;
+use pixy_core::interpolate::{InterpolationOptions, InterpolationPlacement};
+use pixy_core::models::{curated_models, ModelKind, ModelSpec};
+use pixy_core::pipeline::{
+    encodes_video, run_upscale_job, run_upscale_job_with_progress, UpscaleJob,
+};
+use pixy_core::probe::FrameRate;
+use pixy_core::scenes::{ModelRange, SceneModelSelection};
+use pixy_core::temporal::{stabilize_frames, StabilizeMethod, TemporalOptions};
+use pixy_core::test_support::{FakeTools, MockUpscaler};
+use pixy_core::upscalers::{CpuResampler, UpscalerOptions};
+use pixy_core::PixyError;
+
+fn job(tools: &FakeTools, upscaler: &MockUpscaler) -> UpscaleJob {
+    UpscaleJob {
+        input: tools.dir().join("input.mkv"),
+        output: tools.dir().join("output.mkv"),
+        model: ModelSpec {
+            name: "mock-x2".into(),
+            kind: ModelKind::Custom("mock".into()),
+            scale: 2,
+            denoise_level: None,
+            path: None,
+        },
+        upscaler: Arc::new(upscaler.clone()),
+        gpu_index: 0,
+        upscaler_options: UpscalerOptions::default(),
+        tile_fallback: true,
+        target_width: None,
+        target_height: None,
+        scale: None,
+        extract: FrameExtractOptions::default(),
+        encoder: EncoderOptions {
+            rate_control: Some(RateControl::ConstantQuality(20)),
+            pix_fmt: Some("yuv420p".into()),
+            ..EncoderOptions::new(EncoderKind::Libx264)
+        },
+        container: None,
+        audio: AudioOptions::default(),
+        alpha: AlphaMode::Resample,
+        image_output: false,
+        scene_models: None,
+        dedup: None,
+        temporal: None,
+        interpolation: None,
+        work_dir: Some(tools.dir().join("work")),
+    }
+}
+
+#[test]
+fn runs_extract_upscale_encode() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    run_upscale_job(&job(&tools, &upscaler)).unwrap();
+
+    let calls = upscaler.invocations();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].frames.len(), 3);
+
+    let ffmpeg = tools.ffmpeg_calls();
+    assert_eq!(ffmpeg.len(), 2, "extract + encode");
+    let encode = &ffmpeg[1];
+    for pair in [["-map", "0:v:0"], ["-map", "1:a:0"], ["-c:v", "libx264"]] {
+        assert!(
+            encode.windows(2).any(|w| w == pair),
+            "missing {pair:?} in {encode:?}"
+        );
+    }
+    assert!(tools.dir().join("output.mkv").exists());
+}
+
+#[test]
+fn retries_remaining_frames_with_smaller_tiles_on_oom() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    upscaler.fail_when_tile_above(64);
+    run_upscale_job(&job(&tools, &upscaler)).unwrap();
+
+    let calls = upscaler.invocations();
+    let tiles: Vec<_> = calls.iter().map(|c| c.options.tile_size).collect();
+    assert_eq!(tiles, [None, Some(256), Some(128), Some(64)]);
+    // Each retry only sees frames that were not upscaled yet.
+    let pending: Vec<_> = calls.iter().map(|c| c.frames.len()).collect();
+    assert_eq!(pending, [3, 2, 1, 1]);
+}
+
+#[test]
+fn keeps_the_oom_error_when_the_upscaler_has_no_tile_option() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    upscaler.fail_when_tile_above(64);
+    upscaler.without_tile_option();
+    let err = run_upscale_job(&job(&tools, &upscaler)).unwrap_err();
+
+    assert!(matches!(err, PixyError::ProcessFailed { .. }), "{err:?}");
+    assert_eq!(upscaler.invocations().len(), 1);
+}
+
+#[test]
+fn upscales_repeated_frames_once() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    // The fake ffmpeg extracts identical copies of one test frame.
+    let job = UpscaleJob {
+        dedup: Some(DedupOptions::default()),
+        ..job(&tools, &upscaler)
+    };
+    run_upscale_job(&job).unwrap();
+
+    let calls = upscaler.invocations();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].frames.len(), 1);
+    let upscaled = std::fs::read_dir(tools.dir().join("work/upscaled")).unwrap();
+    assert_eq!(upscaled.count(), 3);
+}
+
+#[test]
+fn upscales_time_ranges_with_their_own_model() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    let base = job(&tools, &upscaler);
+    // At 24 fps only the first frame falls inside the range.
+    let range = ModelRange {
+        start: 0.0,
+        end: Some(0.04),
+        model: ModelSpec {
+            name: "mock-alt-x2".into(),
+            ..base.model.clone()
+        },
+    };
+    let job = UpscaleJob {
+        scene_models: Some(SceneModelSelection::Ranges(vec![range])),
+        ..base
+    };
+    let mut upscale_progress = Vec::new();
+    run_upscale_job_with_progress(&job, &mut |p| {
+        if p.stage == "upscale" {
+            upscale_progress.push(p.percent);
+        }
+    })
+    .unwrap();
+    // Each scene reports its own frames: 1 of 1, then 1 and 2 of 2.
+    assert_eq!(upscale_progress, [0.0, 100.0, 0.0, 50.0, 100.0]);
+
+    let calls: Vec<_> = upscaler
+        .invocations()
+        .into_iter()
+        .map(|c| (c.model.name, c.frames.len()))
+        .collect();
+    assert_eq!(
+        calls,
+        [("mock-alt-x2".to_string(), 1), ("mock-x2".to_string(), 2)]
+    );
+    let upscaled = std::fs::read_dir(tools.dir().join("work/upscaled")).unwrap();
+    assert_eq!(upscaled.count(), 3);
+}
+
+#[test]
+fn interpolates_frames_holding_scene_cuts() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    // Frames 1 and 2 are the test frame, frame 3 cuts to black.
+    let black = RgbImage::from_pixel(16, 12, Rgb([0, 0, 0]));
+    tools.set_extracted_frame(3, &black).unwrap();
+    let job = UpscaleJob {
+        interpolation: Some(InterpolationOptions {
+            placement: InterpolationPlacement::BeforeUpscale,
+            ..InterpolationOptions::default()
+        }),
+        ..job(&tools, &upscaler)
+    };
+    run_upscale_job(&job).unwrap();
+
+    let rife = tools.rife_calls();
+    assert_eq!(rife.len(), 1);
+    assert!(
+        rife[0].windows(2).any(|w| w == ["-n", "6"]),
+        "{:?}",
+        rife[0]
+    );
+    // Output 4 sits halfway between frames 2 and 3, across the cut, so it holds frame 3
+    // instead of a blend; output 6 follows frame 3 and is left to RIFE.
+    let interpolated = tools.dir().join("work/interpolated");
+    let frame = |n: u32| {
+        image::open(interpolated.join(format!("{n:08}.png")))
+            .unwrap()
+            .to_rgb8()
+    };
+    assert_eq!(frame(4), black);
+    assert_ne!(frame(6), black);
+    assert_eq!(upscaler.invocations()[0].frames.len(), 6);
+
+    let encode = tools.ffmpeg_calls().pop().unwrap();
+    assert!(
+        encode.windows(2).any(|w| w == ["-framerate", "48/1"]),
+        "{encode:?}"
+    );
+}
+
+#[test]
+fn rejects_scene_thresholds_outside_zero_to_one() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    let job = UpscaleJob {
+        interpolation: Some(InterpolationOptions {
+            scene_threshold: Some(30.0),
+            ..InterpolationOptions::default()
+        }),
+        ..job(&tools, &upscaler)
+    };
+    let err = run_upscale_job(&job).unwrap_err();
+    assert!(matches!(err, PixyError::InvalidArgument(_)), "{err:?}");
+    assert!(tools.ffmpeg_calls().is_empty());
+}
+
+#[test]
+fn keeps_the_cpu_fallback_for_per_scene_models() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    let base = UpscaleJob {
+        upscaler: Arc::new(CpuResampler::default()),
+        ..job(&tools, &upscaler)
+    };
+    // An ncnn model with a denoise level the resampler does not take.
+    let cugan = curated_models()
+        .into_iter()
+        .find(|m| m.name == "realcugan_se_x2")
+        .unwrap();
+    let job = UpscaleJob {
+        scene_models: Some(SceneModelSelection::Ranges(vec![ModelRange {
+            start: 0.0,
+            end: Some(0.04),
+            model: cugan,
+        }])),
+        ..base
+    };
+    run_upscale_job(&job).unwrap();
+
+    let upscaled = std::fs::read_dir(tools.dir().join("work/upscaled")).unwrap();
+    assert_eq!(upscaled.count(), 3);
+}
+
+#[test]
+fn stabilizes_upscaled_frames_before_encoding() {
+    let tools = FakeTools::install().unwrap();
+    let job = UpscaleJob {
+        temporal: Some(TemporalOptions {
+            method: StabilizeMethod::Hqdn3d,
+            strength: 1.0,
+            ..TemporalOptions::default()
+        }),
+        ..job(&tools, &MockUpscaler::new())
+    };
+    run_upscale_job(&job).unwrap();
+
+    let ffmpeg = tools.ffmpeg_calls();
+    assert_eq!(ffmpeg.len(), 3, "extract + stabilize + encode");
+    assert!(ffmpeg[1].contains(&"hqdn3d=0:0:10.0:10.0".to_string()));
+    let stabilized = tools.dir().join("work/stabilized/%08d.png");
+    assert!(ffmpeg[2].contains(&stabilized.to_string_lossy().into_owned()));
+}
+
+#[test]
+fn stabilizes_each_scene_separately() {
+    let tools = FakeTools::install().unwrap();
+    tools
+        .set_extracted_frame(3, &RgbImage::from_pixel(16, 12, Rgb([0, 0, 0])))
+        .unwrap();
+    let job = UpscaleJob {
+        temporal: Some(TemporalOptions::default()),
+        ..job(&tools, &MockUpscaler::new())
+    };
+    run_upscale_job(&job).unwrap();
+
+    let ffmpeg = tools.ffmpeg_calls();
+    assert_eq!(ffmpeg.len(), 4, "extract + two scenes + encode");
+    for (call, start, frames) in [(&ffmpeg[1], "1", "2"), (&ffmpeg[2], "3", "1")] {
+        let starts = call.windows(2).filter(|w| w == &["-start_number", start]);
+        assert_eq!(starts.count(), 2, "{call:?}");
+        assert!(
+            call.windows(2).any(|w| w == ["-frames:v", frames]),
+            "{call:?}"
+        );
+    }
+}
+
+#[test]
+fn writes_numbered_pngs_for_a_sequence_pattern() {
+    let tools = FakeTools::install().unwrap();
+    let output = tools.dir().join("frames-out");
+    let job = UpscaleJob {
+        output: output.join("%06d.png"),
+        ..job(&tools, &MockUpscaler::new())
+    };
+    run_upscale_job(&job).unwrap();
+
+    assert_eq!(tools.ffmpeg_calls().len(), 1, "extract only");
+    for n in 1..=3 {
+        let frame = image::open(output.join(format!("{n:06}.png"))).unwrap();
+        assert_eq!((frame.width(), frame.height()), (32, 24));
+    }
+    assert_eq!(std::fs::read_dir(&output).unwrap().count(), 3);
+}
+
+#[test]
+fn upscales_image_directories_without_ffmpeg() {
+    let tools = FakeTools::install().unwrap();
+    let input = tools.dir().join("stills");
+    std::fs::create_dir_all(&input).unwrap();
+    for name in ["cover.png", "page-02.png"] {
+        std::fs::copy(tools.dir().join("frame.png"), input.join(name)).unwrap();
+    }
+    let upscaler = MockUpscaler::new();
+    let output = tools.dir().join("upscaled-stills");
+    let job = UpscaleJob {
+        input,
+        output: output.clone(),
+        image_output: true,
+        ..job(&tools, &upscaler)
+    };
+    run_upscale_job(&job).unwrap();
+
+    assert!(tools.ffmpeg_calls().is_empty());
+    assert!(tools.ffprobe_calls().is_empty());
+    assert_eq!(upscaler.invocations()[0].frames.len(), 2);
+    let cover = image::open(output.join("cover.png")).unwrap();
+    assert_eq!((cover.width(), cover.height()), (32, 24));
+    assert!(output.join("page-02.png").exists());
+}
+
+#[test]
+fn upscales_animated_gifs_keeping_delays_and_loops() {
+    let tools = FakeTools::install().unwrap();
+    let input = tools.dir().join("sticker.gif");
+    let mut encoder = GifEncoder::new(std::fs::File::create(&input).unwrap());
+    encoder.set_repeat(Repeat::Finite(2)).unwrap();
+    for (i, delay) in [40, 40, 120].into_iter().enumerate() {
+        let buffer = RgbaImage::from_pixel(8, 8, Rgba([i as u8 * 100, 0, 0, 255]));
+        let delay = Delay::from_numer_denom_ms(delay, 1);
+        encoder
+            .encode_frame(Frame::from_parts(buffer, 0, 0, delay))
+            .unwrap();
+    }
+    drop(encoder);
+
+    let upscaler = MockUpscaler::new();
+    let output = tools.dir().join("sticker-2x.gif");
+    let job = UpscaleJob {
+        input,
+        output: output.clone(),
+        ..job(&tools, &upscaler)
+    };
+    run_upscale_job(&job).unwrap();
+
+    assert_eq!(upscaler.invocations()[0].frames.len(), 3);
+    // Opaque frames are upscaled as they are, without an alpha split.
+    assert!(!tools.dir().join("work/rgb").exists());
+    let ffmpeg = tools.ffmpeg_calls();
+    assert_eq!(ffmpeg.len(), 1, "only the animation encode");
+    let encode = &ffmpeg[0];
+    assert!(encode.iter().any(|a| a.contains("palettegen")));
+    // Three plays in total are two repeats in GIF terms.
+    assert!(encode.windows(2).any(|w| w == ["-loop", "2"]));
+    let list = std::fs::read_to_string(tools.dir().join("work/animation.ffconcat")).unwrap();
+    let durations: Vec<_> = list.lines().filter(|l| l.starts_with("duration")).collect();
+    assert_eq!(
+        durations,
+        ["duration 0.040", "duration 0.040", "duration 0.120"]
+    );
+    assert!(output.exists());
+}
+
+#[test]
+fn keeps_transparency_with_an_alpha_capable_encoder() {
+    let tools = FakeTools::install().unwrap();
+    let input = tools.dir().join("cutout");
+    std::fs::create_dir_all(&input).unwrap();
+    RgbaImage::from_fn(8, 8, |x, _| {
+        Rgba([200, 100, 50, if x < 4 { 0 } else { 255 }])
+    })
+    .save(input.join("01.png"))
+    .unwrap();
+    let upscaler = MockUpscaler::new();
+    let job = UpscaleJob {
+        input,
+        output: tools.dir().join("cutout.webm"),
+        ..job(&tools, &upscaler)
+    };
+    run_upscale_job(&job).unwrap();
+
+    // The upscaler only sees opaque RGB; alpha is resampled and merged back.
+    let seen = image::open(&upscaler.invocations()[0].frames[0]).unwrap();
+    assert!(!seen.color().has_alpha());
+    let merged = image::open(tools.dir().join("work/merged/00000001.png"))
+        .unwrap()
+        .to_rgba8();
+    assert_eq!(merged.dimensions(), (16, 16));
+    assert_eq!(merged.get_pixel(0, 8)[3], 0);
+    assert_eq!(merged.get_pixel(15, 8)[3], 255);
+
+    let encode = tools.ffmpeg_calls().pop().unwrap();
+    for pair in [["-c:v", "libvpx-vp9"], ["-pix_fmt", "yuva420p"]] {
+        assert!(
+            encode.windows(2).any(|w| w == pair),
+            "missing {pair:?} in {encode:?}"
+        );
+    }
+}
+
+#[test]
+fn skips_the_alpha_split_for_opaque_images() {
+    let tools = FakeTools::install().unwrap();
+    let input = tools.dir().join("photo");
+    std::fs::create_dir_all(&input).unwrap();
+    RgbImage::from_pixel(8, 8, Rgb([200, 100, 50]))
+        .save(input.join("01.png"))
+        .unwrap();
+    let upscaler = MockUpscaler::new();
+    let job = UpscaleJob {
+        input: input.clone(),
+        output: tools.dir().join("photo.mkv"),
+        ..job(&tools, &upscaler)
+    };
+    run_upscale_job(&job).unwrap();
+
+    assert!(!tools.dir().join("work/rgb").exists());
+    assert!(!tools.dir().join("work/alpha").exists());
+    let seen = &upscaler.invocations()[0].frames[0];
+    assert!(
+        seen.starts_with(tools.dir().join("work/frames")),
+        "{seen:?}"
+    );
+}
+
+#[test]
+fn muxes_to_stdout_with_a_streamable_container() {
+    let tools = FakeTools::install().unwrap();
+    let job = UpscaleJob {
+        output: "-".into(),
+        container: Some(Container::Nut),
+        ..job(&tools, &MockUpscaler::new())
+    };
+    run_upscale_job(&job).unwrap();
+    let encode = tools.ffmpeg_calls().pop().unwrap();
+    assert_eq!(encode[encode.len() - 3..], ["-f", "nut", "-"]);
+
+    let job = UpscaleJob {
+        container: Some(Container::Mp4),
+        ..job
+    };
+    let err = run_upscale_job(&job).unwrap_err();
+    assert!(matches!(err, PixyError::InvalidArgument(_)));
+}
+
+#[test]
+fn surfaces_ffmpeg_failures() {
+    let tools = FakeTools::install().unwrap();
+    tools.fail_ffmpeg(Some("Invalid data found")).unwrap();
+    let err = run_upscale_job(&job(&tools, &MockUpscaler::new())).unwrap_err();
+    let PixyError::ProcessFailed { code, stderr, .. } = err else {
+        panic!("expected ProcessFailed, got {err:?}");
+    };
+    assert_eq!(code, Some(1));
+    assert_eq!(stderr, "Invalid data found");
+}
+
+#[test]
+fn reports_ffmpeg_stderr_from_every_stage() {
+    let tools = FakeTools::install().unwrap();
+    let frames_dir = tools.dir().join("sequence");
+    std::fs::create_dir_all(&frames_dir).unwrap();
+    let frames: Vec<_> = (1..=2)
+        .map(|n| {
+            let frame = frames_dir.join(format!("{n:08}.png"));
+            std::fs::copy(tools.dir().join("frame.png"), &frame).unwrap();
+            frame
+        })
+        .collect();
+    tools.fail_ffmpeg(Some("Conversion failed!")).unwrap();
+
+    let stabilized = stabilize_frames(
+        &frames_dir.join("%08d.png"),
+        &tools.dir().join("stabilized"),
+        FrameRate::new(24, 1),
+        &TemporalOptions::default(),
+        &[],
+    );
+    let animated = encode_animation(
+        &frames,
+        &[100.0, 100.0],
+        LoopCount::Infinite,
+        AnimationFormat::Gif,
+        &tools.dir().join("loop.gif"),
+        &tools.dir().join("gif-work"),
+    );
+    for err in [stabilized.unwrap_err(), animated.unwrap_err()] {
+        assert!(
+            matches!(&err, PixyError::ProcessFailed { stderr, .. } if stderr == "Conversion failed!"),
+            "{err:?}"
+        );
+    }
+}
+
+#[test]
+fn rejects_missing_encoders_before_extracting() {
+    let tools = FakeTools::install().unwrap();
+    tools.set_encoders(&["libx264", "libx265"]).unwrap();
+    let upscaler = MockUpscaler::new();
+    let job = UpscaleJob {
+        encoder: EncoderOptions {
+            encoder: EncoderKind::HevcNvenc,
+            ..job(&tools, &upscaler).encoder
+        },
+        ..job(&tools, &upscaler)
+    };
+    let err = run_upscale_job(&job).unwrap_err();
+    assert!(matches!(
+        err,
+        PixyError::EncoderUnavailable {
+            encoder: "hevc_nvenc",
+            ..
+        }
+    ));
+    assert!(tools.ffmpeg_calls().is_empty());
+    assert!(upscaler.invocations().is_empty());
+
+    // Transparent sources are checked with the encoder that keeps their alpha.
+    tools
+        .set_probe_json(
+            r#"{
+  "streams": [
+    {"index": 0, "codec_type": "video", "codec_name": "prores", "width": 64, "height": 48,
+     "pix_fmt": "yuva444p10le", "r_frame_rate": "24/1", "avg_frame_rate": "24/1"}
+  ],
+  "format": {"format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "1.000000"}
+}"#,
+        )
+        .unwrap();
+    let job = UpscaleJob {
+        output: tools.dir().join("out.webm"),
+        encoder: EncoderOptions::new(EncoderKind::Libx264),
+        ..job
+    };
+    let err = run_upscale_job(&job).unwrap_err();
+    assert!(matches!(
+        err,
+        PixyError::EncoderUnavailable {
+            encoder: "libvpx-vp9",
+            ..
+        }
+    ));
+    assert!(tools.ffmpeg_calls().is_empty());
+    assert!(upscaler.invocations().is_empty());
+}
+
+#[test]
+fn lists_only_hardware_encoders_that_pass_a_test_encode() {
+    let tools = FakeTools::install().unwrap();
+    tools.set_encoders(&["h264_qsv", "libx264"]).unwrap();
+    assert_eq!(
+        available_encoders().unwrap(),
+        [EncoderKind::H264Qsv, EncoderKind::Libx264]
+    );
+    let probe = tools.ffmpeg_calls().pop().unwrap();
+    assert!(probe.windows(2).any(|w| w == ["-c:v", "h264_qsv"]));
+
+    tools.fail_ffmpeg(Some("No device available")).unwrap();
+    assert_eq!(available_encoders().unwrap(), [EncoderKind::Libx264]);
+}
+
+#[test]
+fn only_video_outputs_need_an_encoder() {
+    for output in ["out.mkv", "out.mp4", "-"] {
+        assert!(encodes_video(Path::new(output), false), "{output}");
+    }
+    for output in [
+        "still.png",
+        "loop.gif",
+        "sticker.webp",
+        "frames/%06d.png",
+        "frames/",
+    ] {
+        assert!(!encodes_video(Path::new(output), false), "{output}");
+    }
+    assert!(!encodes_video(Path::new("out.mkv"), true));
+}
+
+#[test]
+fn auto_selects_the_encoder_of_the_gpu_vendor() {
+    let tools = FakeTools::install().unwrap();
+    tools
+        .set_encoders(&["hevc_nvenc", "h264_amf", "libx264", "libx265"])
+        .unwrap();
+    let nvidia = VulkanDevice {
+        index: 0,
+        name: "NVIDIA GeForce RTX 3080".into(),
+        vendor_id: 0x10de,
+    };
+    let choice = auto_select_encoder(Some(&nvidia), None).unwrap();
+    assert_eq!(choice.encoder, EncoderKind::HevcNvenc);
+
+    let amd = VulkanDevice {
+        index: 1,
+        name: "AMD Radeon RX 7900 XTX".into(),
+        vendor_id: 0x1002,
+    };
+    tools.fail_ffmpeg(Some("No AMF device")).unwrap();
+    let choice = auto_select_encoder(Some(&amd), None).unwrap();
+    assert_eq!(choice.encoder, EncoderKind::Libx265);
+    assert!(choice
+        .reason
+        .contains("h264_amf failed a test encode: No AMF device"));
+}
+
+#[test]
+fn uploads_frames_for_vaapi_after_scaling() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    let base = job(&tools, &upscaler);
+    let job = UpscaleJob {
+        target_width: Some(100),
+        encoder: EncoderOptions {
+            encoder: EncoderKind::HevcVaapi,
+            render_node: Some("/dev/dri/renderD129".into()),
+            ..base.encoder.clone()
+        },
+        ..base
+    };
+    run_upscale_job(&job).unwrap();
+
+    let ffmpeg = tools.ffmpeg_calls();
+    let (probe, encode) = (&ffmpeg[0], ffmpeg.last().unwrap());
+    for args in [probe, encode] {
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["-init_hw_device", "vaapi=hw:/dev/dri/renderD129"]));
+    }
+    assert!(encode.windows(2).any(|w| w
+        == [
+            "-vf",
+            "zscale=w=100:h=-1:filter=spline36,format=nv12,hwupload"
+        ]));
+    assert!(!encode.contains(&"-pix_fmt".to_string()));
+}
+
+#[test]
+fn extracts_sixteen_bit_frames_for_ten_bit_encodes() {
+    let tools = FakeTools::install().unwrap();
+    let base = job(&tools, &MockUpscaler::new());
+    let job = UpscaleJob {
+        extract: FrameExtractOptions {
+            high_bit_depth: true,
+            ..base.extract.clone()
+        },
+        encoder: EncoderOptions {
+            encoder: EncoderKind::Libx265,
+            pix_fmt: Some("yuv420p10le".into()),
+            ..base.encoder.clone()
+        },
+        ..base
+    };
+    run_upscale_job(&job).unwrap();
+
+    let ffmpeg = tools.ffmpeg_calls();
+    assert!(ffmpeg[0].windows(2).any(|w| w == ["-pix_fmt", "rgb48be"]));
+    let encode = ffmpeg.last().unwrap();
+    assert!(encode.windows(2).any(|w| w == ["-pix_fmt", "yuv420p10le"]));
+}
+
+#[test]
+fn refuses_pixel_formats_the_encoder_cannot_produce() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    let base = job(&tools, &upscaler);
+    let job = UpscaleJob {
+        encoder: EncoderOptions {
+            encoder: EncoderKind::H264Nvenc,
+            pix_fmt: Some("p010le".into()),
+            ..base.encoder.clone()
+        },
+        ..base
+    };
+    let err = run_upscale_job(&job).unwrap_err();
+    assert!(matches!(err, PixyError::InvalidArgument(_)));
+    assert!(tools.ffmpeg_calls().is_empty());
+    assert!(upscaler.invocations().is_empty());
+}
+
+#[test]
+fn cpu_resampler_keeps_sixteen_bit_frames() {
+    let tools = FakeTools::install().unwrap();
+    let input = tools.dir().join("deep.png");
+    let output = tools.dir().join("deep-x2.png");
+    image::ImageBuffer::from_fn(4, 4, |x, y| {
+        image::Rgb([x as u16 * 4097, y as u16 * 4097, 1])
+    })
+    .save(&input)
+    .unwrap();
+    CpuResampler::default()
+        .upscale_file(&input, &output, 2)
+        .unwrap();
+    let upscaled = image::open(&output).unwrap();
+    assert_eq!(upscaled.color(), image::ColorType::Rgb16);
+    assert_eq!((upscaled.width(), upscaled.height()), (8, 8));
+}
+
+#[test]
+fn encodes_to_a_target_size_in_two_passes() {
+    let tools = FakeTools::install().unwrap();
+    tools
+        .set_probe_json(
+            r#"{
+  "streams": [
+    {"index": 0, "codec_type": "video", "codec_name": "h264", "width": 64, "height": 48,
+     "pix_fmt": "yuv420p", "r_frame_rate": "24/1", "avg_frame_rate": "24/1"},
+    {"index": 1, "codec_type": "audio", "codec_name": "aac", "bit_rate": "128000"}
+  ],
+  "format": {"format_name": "matroska,webm", "duration": "10.000000"}
+}"#,
+        )
+        .unwrap();
+    let base = job(&tools, &MockUpscaler::new());
+    let job = UpscaleJob {
+        encoder: EncoderOptions {
+            rate_control: Some(RateControl::TargetSize { bytes: 10_000_000 }),
+            ..base.encoder.clone()
+        },
+        ..base
+    };
+    run_upscale_job(&job).unwrap();
+
+    let ffmpeg = tools.ffmpeg_calls();
+    assert_eq!(ffmpeg.len(), 3, "extract + two passes");
+    let (first, second) = (&ffmpeg[1], &ffmpeg[2]);
+    // 80 Mbit less 2% overhead over 10 s, less 128 kbit/s of audio.
+    for args in [first, second] {
+        assert!(args.windows(2).any(|w| w == ["-b:v", "7712k"]));
+    }
+    assert!(first.windows(2).any(|w| w == ["-pass", "1"]));
+    assert!(first.ends_with(&["-f".into(), "null".into(), "-".into()]));
+    assert!(!first.contains(&"1:a:0".to_string()));
+    assert!(second.windows(2).any(|w| w == ["-pass", "2"]));
+    assert!(second.windows(2).any(|w| w == ["-map", "1:a:0"]));
+}
+
+#[test]
+fn converts_or_drops_subtitles_the_container_cannot_copy() {
+    let tools = FakeTools::install().unwrap();
+    tools
+        .set_probe_json(
+            r#"{
+  "streams": [
+    {"index": 0, "codec_type": "video", "codec_name": "h264", "width": 64, "height": 48,
+     "pix_fmt": "yuv420p", "r_frame_rate": "24/1", "avg_frame_rate": "24/1"},
+    {"index": 1, "codec_type": "subtitle", "codec_name": "hdmv_pgs_subtitle"},
+    {"index": 2, "codec_type": "subtitle", "codec_name": "ass"},
+    {"index": 3, "codec_type": "attachment", "codec_name": "ttf"}
+  ],
+  "format": {"format_name": "matroska,webm", "duration": "1.000000"}
+}"#,
+        )
+        .unwrap();
+    let job = UpscaleJob {
+        output: tools.dir().join("output.mp4"),
+        ..job(&tools, &MockUpscaler::new())
+    };
+    run_upscale_job(&job).unwrap();
+
+    let encode = tools.ffmpeg_calls().pop().unwrap();
+    let joined = encode.join(" ");
+    assert!(joined.contains("-map 1:s:1 -c:s:0 mov_text"), "{joined}");
+    assert!(!joined.contains("1:s:0"));
+    assert!(!joined.contains("1:t?"));
+    assert!(joined.ends_with(&format!(
+        "-f mp4 -movflags +faststart {}",
+        job.output.display()
+    )));
+}
+
+#[test]
+fn lets_ffmpeg_pick_the_muxer_for_unknown_extensions() {
+    let tools = FakeTools::install().unwrap();
+    tools
+        .set_probe_json(
+            r#"{
+  "streams": [
+    {"index": 0, "codec_type": "video", "codec_name": "h264", "width": 64, "height": 48,
+     "pix_fmt": "yuv420p", "r_frame_rate": "24/1", "avg_frame_rate": "24/1"},
+    {"index": 1, "codec_type": "audio", "codec_name": "ac3", "channels": 6},
+    {"index": 2, "codec_type": "subtitle", "codec_name": "dvb_subtitle"}
+  ],
+  "format": {"format_name": "mpegts", "duration": "1.000000"}
+}"#,
+        )
+        .unwrap();
+    let base = job(&tools, &MockUpscaler::new());
+    let cases = [
+        ("input.mkv", "output.ts", false),
+        ("input.ts", "output.ts", true),
+    ];
+    for (input, output, copies_subtitles) in cases {
+        let job = UpscaleJob {
+            input: tools.dir().join(input),
+            output: tools.dir().join(output),
+            ..base.clone()
+        };
+        run_upscale_job(&job).unwrap();
+
+        let encode = tools.ffmpeg_calls().pop().unwrap();
+        let joined = encode.join(" ");
+        assert!(!encode.contains(&"-f".to_string()), "{joined}");
+        assert!(joined.contains("-map 1:a:0 -c:a:0 copy"), "{joined}");
+        assert_eq!(
+            joined.contains("-map 1:s? -map 1:t?"),
+            copies_subtitles,
+            "{joined}"
+        );
+        assert!(joined.ends_with(&job.output.display().to_string()));
+    }
+}
+
+#[test]
+fn rejects_video_codecs_the_container_cannot_hold() {
+    let tools = FakeTools::install().unwrap();
+    let upscaler = MockUpscaler::new();
+    let job = UpscaleJob {
+        output: tools.dir().join("output.webm"),
+        ..job(&tools, &upscaler)
+    };
+    let err = run_upscale_job(&job).unwrap_err();
+    assert!(matches!(err, PixyError::InvalidArgument(_)));
+    assert!(upscaler.invocations().is_empty());
+}
+
+const TWO_AUDIO_TRACKS_PROBE: &str = r#"{
+  "streams": [
+    {"index": 0, "codec_type": "video", "codec_name": "h264", "width": 64, "height": 48,
+     "pix_fmt": "yuv420p", "r_frame_rate": "24/1", "avg_frame_rate": "24/1"},
+    {"index": 1, "codec_type": "audio", "codec_name": "truehd", "sample_rate": "48000",
+     "tags": {"language": "eng"}},
+    {"index": 2, "codec_type": "audio", "codec_name": "ac3", "sample_rate": "44100",
+     "tags": {"language": "jpn"}}
+  ],
+  "format": {"format_name": "matroska,webm", "duration": "1.000000"}
+}"#;
+
+#[test]
+fn selects_transcodes_and_normalises_audio() {
+    let tools = FakeTools::install().unwrap();
+    tools.set_probe_json(TWO_AUDIO_TRACKS_PROBE).unwrap();
+    let job = UpscaleJob {
+        audio: AudioOptions {
+            selection: AudioSelection::Languages(vec!["JPN".into()]),
+            codec: Some(AudioCodec::Opus),
+            bitrate: Some(96),
+            channels: Some(2),
+            loudness: Some(Loudness::default()),
+        },
+        ..job(&tools, &MockUpscaler::new())
+    };
+    run_upscale_job(&job).unwrap();
+
+    let ffmpeg = tools.ffmpeg_calls();
+    assert_eq!(ffmpeg.len(), 3, "extract + loudness analysis + encode");
+    let analysis = ffmpeg[1].join(" ");
+    assert!(analysis.contains("-map 0:a:1 -af loudnorm=I=-23:TP=-1:LRA=7:print_format=json"));
+    let encode = ffmpeg[2].join(" ");
+    assert!(
+        encode.contains("-map 1:a:1 -c:a:0 libopus -b:a:0 96k -ac:a:0 2"),
+        "{encode}"
+    );
+    assert!(encode.contains(
+        "-filter:a:0 loudnorm=I=-23:TP=-1:LRA=7:measured_I=-31.2:measured_TP=-9.8:measured_LRA=5.4:measured_thresh=-41.7:offset=0.3:linear=true,aresample=44100"
+    ));
+    assert!(!encode.contains("1:a:0"));
+}
+
+#[test]
+fn normalises_silent_audio_in_a_single_pass() {
+    let tools = FakeTools::install().unwrap();
+    tools
+        .set_loudnorm_json(
+            r#"{
+  "input_i" : "-inf",
+  "input_tp" : "-inf",
+  "input_lra" : "0.00",
+  "input_thresh" : "-inf",
+  "target_offset" : "inf"
+}"#,
+        )
+        .unwrap();
+    let job = UpscaleJob {
+        audio: AudioOptions {
+            codec: Some(AudioCodec::Opus),
+            loudness: Some(Loudness::default()),
+            ..AudioOptions::default()
+        },
+        ..job(&tools, &MockUpscaler::new())
+    };
+    run_upscale_job(&job).unwrap();
+
+    let encode = tools.ffmpeg_calls().pop().unwrap().join(" ");
+    assert!(
+        encode.contains("-filter:a:0 loudnorm=I=-23:TP=-1:LRA=7,aresample=48000"),
+        "{encode}"
+    );
+}
+
+#[test]
+fn transcodes_audio_the_container_cannot_store() {
+    let tools = FakeTools::install().unwrap();
+    tools.set_probe_json(TWO_AUDIO_TRACKS_PROBE).unwrap();
+    let job = UpscaleJob {
+        output: tools.dir().join("output.mp4"),
+        ..job(&tools, &MockUpscaler::new())
+    };
+    run_upscale_job(&job).unwrap();
+
+    let encode = tools.ffmpeg_calls().pop().unwrap().join(" ");
+    assert!(
+        encode.contains("-map 1:a:0 -c:a:0 aac -b:a:0 192k"),
+        "{encode}"
+    );
+    assert!(encode.contains("-map 1:a:1 -c:a:1 copy"));
+}
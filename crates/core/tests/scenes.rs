@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use image::{Rgb, RgbImage};
+use pixy_core::scenes::{classify_frame, ContentClass, ModelRange};
+use pixy_core::PixyError;
+
+fn save(name: &str, frame: &RgbImage) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("pixy-uppy-{name}-{}.png", std::process::id()));
+    frame.save(&path).unwrap();
+    path
+}
+
+#[test]
+fn classifies_flat_fills_as_animation() {
+    // Two cel-style fills with a hard edge.
+    let cel = RgbImage::from_fn(320, 180, |x, _| {
+        if x < 160 {
+            Rgb([250, 200, 180])
+        } else {
+            Rgb([40, 60, 120])
+        }
+    });
+    // Pseudo-random grain on every pixel, like camera noise.
+    let grain = RgbImage::from_fn(320, 180, |x, y| {
+        let n = (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729)).wrapping_mul(2_654_435_761) >> 24;
+        Rgb([n as u8, n as u8, n as u8])
+    });
+    let black = RgbImage::new(320, 180);
+
+    for (name, frame, class) in [
+        ("cel", cel, ContentClass::Animation),
+        ("grain", grain, ContentClass::LiveAction),
+        ("black", black, ContentClass::Animation),
+    ] {
+        let path = save(name, &frame);
+        assert_eq!(classify_frame(&path).unwrap(), class, "{name}");
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn parses_range_files_with_seconds_and_timestamps() {
+    let ranges = ModelRange::from_toml_str(
+        r#"
+[[range]]
+start = 0
+end = "1:30.5"
+model = "realesrgan-x4plus"
+
+[[range]]
+start = "1:02:03"
+model = "realcugan_se_x2"
+"#,
+    )
+    .unwrap();
+    assert_eq!(ranges.len(), 2);
+    assert_eq!((ranges[0].start, ranges[0].end), (0.0, Some(90.5)));
+    assert_eq!(ranges[0].model.name, "realesrgan-x4plus");
+    assert_eq!((ranges[1].start, ranges[1].end), (3723.0, None));
+    assert_eq!(ranges[1].model.name, "realcugan_se_x2");
+}
+
+#[test]
+fn rejects_invalid_ranges() {
+    let range = |start: &str, end: &str, model: &str| {
+        ModelRange::from_toml_str(&format!(
+            "[[range]]\nstart = {start}\nend = {end}\nmodel = \"{model}\"\n"
+        ))
+    };
+    for (start, end, model) in [
+        (r#""1:xx""#, "5", "realesrgan-x4plus"),
+        ("0", r#""-0:05""#, "realesrgan-x4plus"),
+        ("0", r#""nan""#, "realesrgan-x4plus"),
+        ("10", "5", "realesrgan-x4plus"),
+        ("0", "5", "no-such-model"),
+    ] {
+        assert!(
+            matches!(range(start, end, model), Err(PixyError::InvalidArgument(_))),
+            "{start}..{end} {model}"
+        );
+    }
+    assert!(matches!(
+        ModelRange::from_toml_str("[[range]]\nstart = 0\n"),
+        Err(PixyError::Toml(_))
+    ));
+}
@@ -0,0 +1,345 @@
+use std::path::{Path, PathBuf};
+
+use image::{Rgb, RgbImage};
+use pixy_core::devices::VulkanDevice;
+use pixy_core::models::{ModelKind, ModelSpec};
+use pixy_core::test_support::MockUpscaler;
+use pixy_core::upscalers::log::parse_line;
+use pixy_core::upscalers::{
+    cpu_fallback_reason, CpuResampler, RealCugan, RealEsrgan, ResampleFilter, TemplateBackend,
+    UpscaleRequest, UpscaledFormat, UpscalerBackend, UpscalerEvent, UpscalerFailure,
+    UpscalerOptions, Waifu2x,
+};
+use pixy_core::PixyError;
+
+#[test]
+fn parses_ncnn_progress_and_done_lines() {
+    assert_eq!(
+        parse_line("45.83%"),
+        UpscalerEvent::Progress { percent: 45.83 }
+    );
+    assert_eq!(
+        parse_line("/tmp/frames/00000001.png -> /tmp/up/00000001.png done"),
+        UpscalerEvent::FileDone {
+            input: PathBuf::from("/tmp/frames/00000001.png"),
+            output: PathBuf::from("/tmp/up/00000001.png"),
+        }
+    );
+    let device = "[0 NVIDIA GeForce RTX 3080]  queueC=2[8]  queueG=0[16]  queueT=1[2]";
+    assert_eq!(parse_line(device), UpscalerEvent::Log(device.into()));
+}
+
+#[test]
+fn classifies_ncnn_failures() {
+    for (log, failure) in [
+        (
+            "vkAllocateMemory failed -2",
+            Some(UpscalerFailure::OutOfMemory),
+        ),
+        (
+            "vkQueueSubmit failed -4\nVK_ERROR_OUT_OF_DEVICE_MEMORY",
+            Some(UpscalerFailure::OutOfMemory),
+        ),
+        (
+            "invalid gpu device",
+            Some(UpscalerFailure::UnsupportedDevice),
+        ),
+        (
+            "fopen models/realesrgan-x4plus.param failed\nnetwork graph not ready",
+            Some(UpscalerFailure::MissingModel),
+        ),
+        (
+            "fopen models-cunet/noise0_scale2.0x_model.bin failed",
+            Some(UpscalerFailure::MissingModel),
+        ),
+        ("fopen /tmp/frames/00000001.png failed", None),
+        ("decode image /tmp/frames/00000001.png failed", None),
+    ] {
+        assert_eq!(UpscalerFailure::classify(log), failure, "{log}");
+    }
+}
+
+fn model(kind: ModelKind, name: &str, scale: u32, denoise_level: Option<u8>) -> ModelSpec {
+    ModelSpec {
+        name: name.into(),
+        kind,
+        scale,
+        denoise_level,
+        path: None,
+    }
+}
+
+fn args(backend: &dyn UpscalerBackend, model: &ModelSpec, options: &UpscalerOptions) -> String {
+    let request = UpscaleRequest {
+        input_pattern: Path::new("/work/frames/%08d.png"),
+        output_pattern: Path::new("/work/upscaled/%08d.png"),
+        gpu: 1,
+        options,
+        model,
+    };
+    let args = backend.build_args(&request).unwrap();
+    args.iter()
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn builds_realesrgan_args() {
+    let options = UpscalerOptions {
+        tile_size: Some(256),
+        proc_threads: Some(4),
+        tta: true,
+        output_format: Some(UpscaledFormat::Webp),
+        ..UpscalerOptions::default()
+    };
+    let anime = model(ModelKind::RealEsrgan, "realesr-animevideov3-x2", 2, None);
+    assert_eq!(
+        args(&RealEsrgan, &anime, &options),
+        "-i /work/frames -o /work/upscaled -g 1 -n realesr-animevideov3 -s 2 -t 256 -j 1:4:2 -x -f webp -v"
+    );
+    let custom = ModelSpec {
+        path: Some("/models".into()),
+        ..model(ModelKind::RealEsrgan, "realesrgan-x4plus", 4, None)
+    };
+    assert_eq!(
+        args(&RealEsrgan, &custom, &UpscalerOptions::default()),
+        "-i /work/frames -o /work/upscaled -g 1 -n realesrgan-x4plus -s 4 -m /models -v"
+    );
+}
+
+#[test]
+fn builds_realcugan_args() {
+    let denoised = model(ModelKind::RealCugan, "realcugan", 2, Some(3));
+    assert_eq!(
+        args(&RealCugan, &denoised, &UpscalerOptions::default()),
+        "-i /work/frames -o /work/upscaled -g 1 -s 2 -n 3 -v"
+    );
+    let default = model(ModelKind::RealCugan, "realcugan", 4, None);
+    assert_eq!(
+        args(&RealCugan, &default, &UpscalerOptions::default()),
+        "-i /work/frames -o /work/upscaled -g 1 -s 4 -v"
+    );
+}
+
+#[test]
+fn builds_waifu2x_args_keeping_the_default_denoise() {
+    let default = model(ModelKind::Waifu2x, "waifu2x", 2, None);
+    assert_eq!(
+        args(&Waifu2x, &default, &UpscalerOptions::default()),
+        "-i /work/frames -o /work/upscaled -g 1 -s 2 -v"
+    );
+    let denoised = model(ModelKind::Waifu2x, "waifu2x", 2, Some(1));
+    assert_eq!(
+        args(&Waifu2x, &denoised, &UpscalerOptions::default()),
+        "-i /work/frames -o /work/upscaled -g 1 -s 2 -n 1 -v"
+    );
+}
+
+const TEMPLATE: &str = r#"
+id = "anime4k"
+binary = "anime4k-cli"
+args = ["-i", "{input}", "-o", "{output_pattern}", "-z", "{scale}", "--model={model}",
+        ["-t", "{tile}"], ["-n", "{denoise}"]]
+progress_regex = 'progress: (?P<percent>\d+(\.\d+)?)%'
+done_regex = '^wrote (?P<input>\S+) as (?P<output>\S+)$'
+scales = [2, 4]
+"#;
+
+#[test]
+fn expands_template_placeholders_and_drops_empty_groups() {
+    let backend = TemplateBackend::from_toml_str(TEMPLATE).unwrap();
+    let model = model(ModelKind::Custom("anime4k".into()), "acnet", 2, None);
+    assert_eq!(
+        args(&backend, &model, &UpscalerOptions::default()),
+        "-i /work/frames -o /work/upscaled/%08d.png -z 2 --model=acnet"
+    );
+    let tiled = UpscalerOptions {
+        tile_size: Some(128),
+        ..UpscalerOptions::default()
+    };
+    assert_eq!(
+        args(&backend, &model, &tiled),
+        "-i /work/frames -o /work/upscaled/%08d.png -z 2 --model=acnet -t 128"
+    );
+    assert!(backend.capabilities().tile_size);
+    assert!(!backend.capabilities().gpu_selection);
+}
+
+#[test]
+fn parses_template_progress_and_done_lines() {
+    let backend = TemplateBackend::from_toml_str(TEMPLATE).unwrap();
+    assert_eq!(
+        backend.parse_progress("progress: 12.5%"),
+        UpscalerEvent::Progress { percent: 12.5 }
+    );
+    assert_eq!(
+        backend.parse_progress("wrote a.png as b.png"),
+        UpscalerEvent::FileDone {
+            input: PathBuf::from("a.png"),
+            output: PathBuf::from("b.png"),
+        }
+    );
+    assert_eq!(
+        backend.parse_progress("12.5%"),
+        UpscalerEvent::Log("12.5%".into())
+    );
+}
+
+#[test]
+fn rejects_broken_templates() {
+    let with = |line: &str| format!("id = \"x\"\nbinary = \"x\"\n{line}");
+    assert!(matches!(
+        TemplateBackend::from_toml_str("id = "),
+        Err(PixyError::Toml(_))
+    ));
+    assert!(matches!(
+        TemplateBackend::from_toml_str(&with("args = []\nprogress_regex = '('")),
+        Err(PixyError::Regex(_))
+    ));
+    assert!(matches!(
+        TemplateBackend::from_toml_str(&with("args = []\nprogress_regex = '\\d+%'")),
+        Err(PixyError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        TemplateBackend::from_toml_str(&with("args = [\"{frames}\"]")),
+        Err(PixyError::InvalidArgument(_))
+    ));
+
+    // A placeholder without a value outside an optional group cannot be expanded.
+    let backend = TemplateBackend::from_toml_str(&with("args = [\"-t\", \"{tile}\"]")).unwrap();
+    let model = model(ModelKind::Custom("x".into()), "x", 2, None);
+    let request = UpscaleRequest {
+        input_pattern: Path::new("/work/frames/%08d.png"),
+        output_pattern: Path::new("/work/upscaled/%08d.png"),
+        gpu: 0,
+        options: &UpscalerOptions::default(),
+        model: &model,
+    };
+    assert!(matches!(
+        backend.build_args(&request),
+        Err(PixyError::InvalidArgument(_))
+    ));
+}
+
+/// Fresh scratch directory for one test.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pixy-uppy-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn checkerboard(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        if (x + y) % 2 == 0 {
+            Rgb([255, 255, 255])
+        } else {
+            Rgb([0, 0, 0])
+        }
+    })
+}
+
+#[test]
+fn cpu_resampler_scales_every_frame() {
+    let dir = scratch("cpu-scale");
+    let frames = dir.join("frames");
+    std::fs::create_dir_all(&frames).unwrap();
+    for i in 1..=2 {
+        checkerboard(8, 6)
+            .save(frames.join(format!("{i:08}.png")))
+            .unwrap();
+    }
+
+    for scale in [2, 3] {
+        let output = dir.join(format!("x{scale}"));
+        let spec = model(ModelKind::Custom("cpu".into()), "lanczos3", scale, None);
+        let options = UpscalerOptions::default();
+        let request = UpscaleRequest {
+            input_pattern: &frames.join("%08d.png"),
+            output_pattern: &output.join("%08d.png"),
+            gpu: 0,
+            options: &options,
+            model: &spec,
+        };
+        let mut done = 0;
+        CpuResampler::default()
+            .run(&request, &mut |event| {
+                if let UpscalerEvent::FileDone { .. } = event {
+                    done += 1;
+                }
+            })
+            .unwrap();
+        assert_eq!(done, 2);
+        for i in 1..=2 {
+            let frame = image::open(output.join(format!("{i:08}.png"))).unwrap();
+            assert_eq!((frame.width(), frame.height()), (8 * scale, 6 * scale));
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cpu_resampler_filters_produce_different_pixels() {
+    let dir = scratch("cpu-filters");
+    let input = dir.join("board.png");
+    checkerboard(6, 6).save(&input).unwrap();
+
+    let filters = [
+        ResampleFilter::Lanczos3,
+        ResampleFilter::Bicubic,
+        ResampleFilter::EwaLanczos,
+    ];
+    let outputs: Vec<Vec<u8>> = filters
+        .iter()
+        .map(|&filter| {
+            let output = dir.join(format!("{filter:?}.png"));
+            let resampler = CpuResampler {
+                filter,
+                ..CpuResampler::default()
+            };
+            resampler.upscale_file(&input, &output, 2).unwrap();
+            image::open(&output).unwrap().to_rgb8().into_raw()
+        })
+        .collect();
+    assert_ne!(outputs[0], outputs[1]);
+    assert_ne!(outputs[0], outputs[2]);
+    assert_ne!(outputs[1], outputs[2]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn device(name: &str, vendor_id: u32) -> VulkanDevice {
+    VulkanDevice {
+        index: 0,
+        name: name.into(),
+        vendor_id,
+    }
+}
+
+#[test]
+fn falls_back_to_the_cpu_on_software_vulkan() {
+    let llvmpipe = device("llvmpipe (LLVM 17.0.6, 256 bits)", 0x10005);
+    let reason = cpu_fallback_reason(&[llvmpipe], &MockUpscaler::new());
+    assert_eq!(reason.as_deref(), Some("no hardware Vulkan device found"));
+    assert!(cpu_fallback_reason(&[], &MockUpscaler::new()).is_some());
+}
+
+#[test]
+fn falls_back_to_the_cpu_without_the_backend_binary() {
+    let gpu = device("NVIDIA GeForce RTX 3060", 0x10de);
+    let missing = TemplateBackend::from_toml_str(
+        r#"
+id = "missing"
+binary = "pixy-uppy-no-such-upscaler"
+args = ["-i", "{input}", "-o", "{output_pattern}"]
+"#,
+    )
+    .unwrap();
+    let reason = cpu_fallback_reason(std::slice::from_ref(&gpu), &missing);
+    assert_eq!(
+        reason.as_deref(),
+        Some("missing binary not found"),
+        "a hardware device does not help without the binary"
+    );
+    assert_eq!(cpu_fallback_reason(&[gpu], &MockUpscaler::new()), None);
+}